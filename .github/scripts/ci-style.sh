@@ -7,6 +7,9 @@ export RUSTFLAGS="-D warnings"
 pushd $BINDING_PATH/mmtk
 cargo clippy
 cargo clippy --release
+cargo clippy --tests
+
+cargo test
 
 cargo fmt -- --check
 popd
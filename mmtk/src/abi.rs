@@ -1,5 +1,5 @@
-use super::UPCALLS;
-use crate::OpenJDKSlot;
+use crate::upcalls;
+use crate::{KlassLayoutClosure, OpenJDKSlot};
 use atomic::Atomic;
 use atomic::Ordering;
 use mmtk::util::constants::*;
@@ -9,7 +9,7 @@ use mmtk::util::{Address, OpaquePointer};
 use std::ffi::CStr;
 use std::fmt;
 use std::sync::atomic::AtomicUsize;
-use std::{mem, slice};
+use std::{mem, ptr, slice};
 
 // These are some Java specific constants that were in MMTk.
 // As we plan to remove them from MMTk, they are moved here.
@@ -92,6 +92,8 @@ impl Klass {
     #[allow(clippy::erasing_op)]
     pub const LH_LOG2_ELEMENT_SIZE_SHIFT: i32 = BITS_IN_BYTE as i32 * 0;
     pub const LH_LOG2_ELEMENT_SIZE_MASK: i32 = BITS_IN_LONG as i32 - 1;
+    pub const LH_ELEMENT_TYPE_SHIFT: i32 = BITS_IN_BYTE as i32;
+    pub const LH_ELEMENT_TYPE_MASK: i32 = (1 << BITS_IN_BYTE) - 1;
     pub const LH_HEADER_SIZE_SHIFT: i32 = BITS_IN_BYTE as i32 * 2;
     pub const LH_HEADER_SIZE_MASK: i32 = (1 << BITS_IN_BYTE) - 1;
     pub unsafe fn cast<'a, T>(&self) -> &'a T {
@@ -105,10 +107,45 @@ impl Klass {
     const fn layout_helper_log2_element_size(lh: i32) -> i32 {
         (lh >> Self::LH_LOG2_ELEMENT_SIZE_SHIFT) & Self::LH_LOG2_ELEMENT_SIZE_MASK
     }
+    /// Get array element type
+    const fn layout_helper_element_type(lh: i32) -> i32 {
+        (lh >> Self::LH_ELEMENT_TYPE_SHIFT) & Self::LH_ELEMENT_TYPE_MASK
+    }
     /// Get array header size
     const fn layout_helper_header_size(lh: i32) -> i32 {
         (lh >> Self::LH_HEADER_SIZE_SHIFT) & Self::LH_HEADER_SIZE_MASK
     }
+    /// Get the super class. Returns `None` only for `java.lang.Object`.  Unlike
+    /// `Class.getSuperclass()`, HotSpot sets the super class of interfaces to `java.lang.Object`.
+    pub fn super_klass(&self) -> Option<&'static Klass> {
+        // `super_` may be null, so we must not read it as a reference.
+        unsafe {
+            (ptr::addr_of!(self.super_) as *const *const Klass)
+                .read()
+                .as_ref()
+        }
+    }
+    /// Get the `java.lang.Class` instance of this class, if it has been created.
+    pub fn java_mirror(&self) -> Option<ObjectReference> {
+        let handle = unsafe { (ptr::addr_of!(self.java_mirror) as *const Address).read() };
+        if handle.is_zero() {
+            None
+        } else {
+            ObjectReference::from_raw_address(unsafe { handle.load::<Address>() })
+        }
+    }
+    /// Get the offset of the first element of arrays of this array class
+    pub fn array_header_in_bytes(&self) -> usize {
+        Self::layout_helper_header_size(self.layout_helper) as usize
+    }
+    /// Get the element type of this array class, in the numbering of `BasicType`
+    pub fn array_element_type(&self) -> i32 {
+        Self::layout_helper_element_type(self.layout_helper)
+    }
+    /// Get log2 of the element size of this array class
+    pub fn array_log2_element_size(&self) -> usize {
+        Self::layout_helper_log2_element_size(self.layout_helper) as usize
+    }
 }
 
 #[repr(C)]
@@ -211,26 +248,31 @@ pub struct InstanceMirrorKlass {
 }
 
 impl InstanceMirrorKlass {
-    fn offset_of_static_fields() -> usize {
+    pub(crate) fn offset_of_static_fields() -> usize {
         lazy_static! {
             pub static ref OFFSET_OF_STATIC_FIELDS: usize =
-                unsafe { ((*UPCALLS).offset_of_static_fields)() as usize };
+                (upcalls().offset_of_static_fields)() as usize;
         }
         *OFFSET_OF_STATIC_FIELDS
     }
-    fn static_oop_field_count_offset() -> i32 {
+    pub(crate) fn static_oop_field_count_offset() -> i32 {
         lazy_static! {
             pub static ref STATIC_OOP_FIELD_COUNT_OFFSET: i32 =
-                unsafe { ((*UPCALLS).static_oop_field_count_offset)() };
+                (upcalls().static_oop_field_count_offset)();
         }
         *STATIC_OOP_FIELD_COUNT_OFFSET
     }
-    pub fn start_of_static_fields(oop: Oop) -> Address {
-        Address::from_ref(oop) + Self::offset_of_static_fields()
+    fn klass_offset() -> i32 {
+        lazy_static! {
+            pub static ref KLASS_OFFSET: i32 = (upcalls().mirror_klass_offset)();
+        }
+        *KLASS_OFFSET
     }
-    pub fn static_oop_field_count(oop: Oop) -> usize {
-        let offset = Self::static_oop_field_count_offset();
-        unsafe { oop.get_field_address(offset).load::<i32>() as _ }
+    /// Get the class represented by a `java.lang.Class` instance.
+    /// Returns `None` for the mirrors of primitive types.
+    pub fn klass_of_mirror(oop: Oop) -> Option<&'static Klass> {
+        let klass_addr = oop.get_field_address(Self::klass_offset());
+        unsafe { klass_addr.load::<*const Klass>().as_ref() }
     }
 }
 
@@ -268,22 +310,31 @@ pub struct InstanceRefKlass {
 impl InstanceRefKlass {
     fn referent_offset() -> i32 {
         lazy_static! {
-            pub static ref REFERENT_OFFSET: i32 = unsafe { ((*UPCALLS).referent_offset)() };
+            pub static ref REFERENT_OFFSET: i32 = (upcalls().referent_offset)();
         }
         *REFERENT_OFFSET
     }
     fn discovered_offset() -> i32 {
         lazy_static! {
-            pub static ref DISCOVERED_OFFSET: i32 = unsafe { ((*UPCALLS).discovered_offset)() };
+            pub static ref DISCOVERED_OFFSET: i32 = (upcalls().discovered_offset)();
         }
         *DISCOVERED_OFFSET
     }
+    fn next_offset() -> i32 {
+        lazy_static! {
+            pub static ref NEXT_OFFSET: i32 = (upcalls().next_offset)();
+        }
+        *NEXT_OFFSET
+    }
     pub fn referent_address<const COMPRESSED: bool>(oop: Oop) -> OpenJDKSlot<COMPRESSED> {
         oop.get_field_address(Self::referent_offset()).into()
     }
     pub fn discovered_address<const COMPRESSED: bool>(oop: Oop) -> OpenJDKSlot<COMPRESSED> {
         oop.get_field_address(Self::discovered_offset()).into()
     }
+    pub fn next_address<const COMPRESSED: bool>(oop: Oop) -> OpenJDKSlot<COMPRESSED> {
+        oop.get_field_address(Self::next_offset()).into()
+    }
 }
 
 #[repr(C)]
@@ -315,6 +366,17 @@ impl OopDesc {
         unsafe { mem::transmute(self) }
     }
 
+    /// Return false if the klass pointer of the object is null.
+    pub fn has_klass<const COMPRESSED: bool>(&self) -> bool {
+        // Read the raw bits.  A null `&'static Klass` must not be created.
+        let klass_addr = self.start() + memoffset::offset_of!(OopDesc, klass);
+        if COMPRESSED {
+            unsafe { klass_addr.load::<u32>() != 0 }
+        } else {
+            unsafe { klass_addr.load::<usize>() != 0 }
+        }
+    }
+
     pub fn klass<const COMPRESSED: bool>(&self) -> &'static Klass {
         if COMPRESSED {
             let compressed = unsafe { self.klass.narrow_klass };
@@ -330,7 +392,7 @@ impl OopDesc {
 impl fmt::Debug for OopDesc {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let c_string = unsafe {
-            ((*UPCALLS).dump_object_string)(mem::transmute::<&OopDesc, ObjectReference>(self))
+            (upcalls().dump_object_string)(mem::transmute::<&OopDesc, ObjectReference>(self))
         };
         let c_str: &CStr = unsafe { CStr::from_ptr(c_string) };
         let s: &str = c_str.to_str().unwrap();
@@ -381,7 +443,7 @@ impl OopDesc {
 
     /// Slow-path for calculating object instance size
     unsafe fn size_slow(&self) -> usize {
-        ((*UPCALLS).get_object_size)(self.into())
+        (upcalls().get_object_size)(self.into())
     }
 
     /// Calculate object instance size
@@ -402,7 +464,7 @@ impl OopDesc {
                 let mut size_in_bytes: usize =
                     (array_length as usize) << Klass::layout_helper_log2_element_size(lh);
                 size_in_bytes += Klass::layout_helper_header_size(lh) as usize;
-                (size_in_bytes + 0b111) & !0b111
+                conversions::raw_align_up(size_in_bytes, crate::object_model::object_alignment())
             } else {
                 self.size_slow()
             }
@@ -442,7 +504,7 @@ impl ArrayOopDesc {
             typesize_in_bytes / BYTES_IN_WORD
         }
     }
-    fn length<const COMPRESSED: bool>(&self) -> i32 {
+    pub fn length<const COMPRESSED: bool>(&self) -> i32 {
         unsafe { (Address::from_ref(self) + Self::length_offset::<COMPRESSED>()).load::<i32>() }
     }
     fn base<const COMPRESSED: bool>(&self, ty: BasicType) -> Address {
@@ -479,16 +541,109 @@ pub struct OopMapBlock {
     pub count: u32,
 }
 
+/// The binding's value of a layout entry that does not depend on a klass: the size of a struct, or
+/// the offset of a field.  Returns `None` if the binding does not know the entry.
+fn layout_value<const COMPRESSED: bool>(name: &str) -> Option<isize> {
+    let value = match name {
+        "sizeof(Klass)" => mem::size_of::<Klass>(),
+        "sizeof(InstanceKlass)" => mem::size_of::<InstanceKlass>(),
+        "sizeof(InstanceRefKlass)" => mem::size_of::<InstanceRefKlass>(),
+        "sizeof(InstanceMirrorKlass)" => mem::size_of::<InstanceMirrorKlass>(),
+        "sizeof(InstanceClassLoaderKlass)" => mem::size_of::<InstanceClassLoaderKlass>(),
+        "sizeof(TypeArrayKlass)" => mem::size_of::<TypeArrayKlass>(),
+        "sizeof(ObjArrayKlass)" => mem::size_of::<ObjArrayKlass>(),
+        "Klass::_layout_helper" => memoffset::offset_of!(Klass, layout_helper),
+        "Klass::_super" => memoffset::offset_of!(Klass, super_),
+        "Klass::_java_mirror" => memoffset::offset_of!(Klass, java_mirror),
+        "Klass::_vtable_len" => memoffset::offset_of!(Klass, vtable_len),
+        "InstanceKlass::vtable_start_offset()" => InstanceKlass::VTABLE_START_OFFSET,
+        "oopDesc::klass_offset_in_bytes()" => memoffset::offset_of!(OopDesc, klass),
+        "arrayOopDesc::length_offset_in_bytes()" => ArrayOopDesc::length_offset::<COMPRESSED>(),
+        "arrayOopDesc::base_offset_in_bytes(T_OBJECT)" => {
+            ArrayOopDesc::header_size::<COMPRESSED>(BasicType::T_OBJECT) * BYTES_IN_WORD
+        }
+        _ => return None,
+    };
+    Some(value as isize)
+}
+
+/// The binding's value of a layout entry read from `klass`.  Fields without an offset accessor in
+/// HotSpot are checked this way, by comparing what both sides read from the same klasses.
+/// Returns `None` if the binding does not know the entry.
+fn klass_layout_value(name: &str, klass: &'static Klass) -> Option<isize> {
+    // The VM only reports the `InstanceKlass` entries for instance klasses.
+    let instance_klass = || unsafe { klass.cast::<InstanceKlass>() };
+    let value = match name {
+        "Klass::id()" => klass.id as isize,
+        "Klass::layout_helper()" => klass.layout_helper as isize,
+        "Klass::vtable_length()" => klass.vtable_len as isize,
+        "Klass::super()" => klass
+            .super_klass()
+            .map_or(0, |k| Address::from_ref(k).as_usize() as isize),
+        "Klass::java_mirror()" => klass
+            .java_mirror()
+            .map_or(0, |o| o.to_raw_address().as_usize() as isize),
+        "InstanceKlass::itable_length()" => instance_klass().itable_len as isize,
+        "InstanceKlass::nonstatic_oop_map_count()" => {
+            instance_klass().nonstatic_oop_map_count() as isize
+        }
+        "InstanceKlass::reference_type()" => instance_klass().reference_type as isize,
+        "InstanceKlass::start_of_nonstatic_oop_maps()" => {
+            let oop_maps = Address::from_ptr(instance_klass().nonstatic_oop_maps().as_ptr());
+            (oop_maps - Address::from_ref(klass)) as isize
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Compare the layout entries reported by `report` with the structs in this module.  Returns a
+/// description of each mismatch.
+pub(crate) fn check_memory_layouts<const COMPRESSED: bool>(
+    report: impl FnOnce(KlassLayoutClosure),
+) -> Vec<String> {
+    let mut errors = vec![];
+    let mut checked = 0;
+    let mut callback = |name: &CStr, vm_value: isize, klass: Option<&'static Klass>| {
+        let name = name.to_string_lossy();
+        let (binding_value, what) = match klass {
+            Some(klass) => (
+                klass_layout_value(&name, klass),
+                format!("{} of the klass at {}", name, Address::from_ref(klass)),
+            ),
+            None => (layout_value::<COMPRESSED>(&name), name.to_string()),
+        };
+        match binding_value {
+            Some(binding_value) if binding_value != vm_value => errors.push(format!(
+                "{}: VM = {}, binding = {}",
+                what, vm_value, binding_value
+            )),
+            Some(_) => checked += 1,
+            None => log::warn!("The binding does not check the layout entry {}", what),
+        }
+    };
+    report(KlassLayoutClosure::from_rust_closure(&mut callback));
+    if checked == 0 && errors.is_empty() {
+        errors.push("the VM did not report any layout entry".to_string());
+    }
+    errors
+}
+
+/// Check that the structs in this module match the memory layout of klasses and objects in the
+/// VM.  Each mismatching field is reported by name.
 pub fn validate_memory_layouts() {
-    let vm_checksum = unsafe { ((*UPCALLS).compute_klass_mem_layout_checksum)() };
-    let binding_checksum = {
-        mem::size_of::<Klass>()
-            ^ mem::size_of::<InstanceKlass>()
-            ^ mem::size_of::<InstanceRefKlass>()
-            ^ mem::size_of::<InstanceMirrorKlass>()
-            ^ mem::size_of::<InstanceClassLoaderKlass>()
-            ^ mem::size_of::<TypeArrayKlass>()
-            ^ mem::size_of::<ObjArrayKlass>()
+    let report = |closure| (upcalls().report_klass_mem_layout)(closure);
+    let errors = if crate::use_compressed_oops() {
+        check_memory_layouts::<true>(report)
+    } else {
+        check_memory_layouts::<false>(report)
     };
-    assert_eq!(vm_checksum, binding_checksum);
+    for error in &errors {
+        log::error!("Klass layout mismatch: {}", error);
+    }
+    assert!(
+        errors.is_empty(),
+        "The binding does not match the memory layout of the VM: {}",
+        errors.join("; ")
+    );
 }
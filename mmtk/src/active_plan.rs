@@ -1,6 +1,6 @@
+use crate::upcalls;
 use crate::MutatorClosure;
 use crate::OpenJDK;
-use crate::UPCALLS;
 use mmtk::util::opaque_pointer::*;
 use mmtk::vm::ActivePlan;
 use mmtk::Mutator;
@@ -15,11 +15,9 @@ struct OpenJDKMutatorIterator<'a, const COMPRESSED: bool> {
 impl<const COMPRESSED: bool> OpenJDKMutatorIterator<'_, COMPRESSED> {
     fn new() -> Self {
         let mut mutators = VecDeque::new();
-        unsafe {
-            ((*UPCALLS).get_mutators)(MutatorClosure::from_rust_closure(&mut |mutator| {
-                mutators.push_back(mutator);
-            }));
-        }
+        (upcalls().get_mutators)(MutatorClosure::from_rust_closure(&mut |mutator| {
+            mutators.push_back(mutator);
+        }));
         Self {
             mutators,
             phantom_data: PhantomData,
@@ -39,14 +37,12 @@ pub struct VMActivePlan {}
 
 impl<const COMPRESSED: bool> ActivePlan<OpenJDK<COMPRESSED>> for VMActivePlan {
     fn is_mutator(tls: VMThread) -> bool {
-        unsafe { ((*UPCALLS).is_mutator)(tls) }
+        (upcalls().is_mutator)(tls)
     }
 
     fn mutator(tls: VMMutatorThread) -> &'static mut Mutator<OpenJDK<COMPRESSED>> {
-        unsafe {
-            let m = ((*UPCALLS).get_mmtk_mutator)(tls);
-            &mut *(m as *mut Mutator<OpenJDK<COMPRESSED>>)
-        }
+        let m = (upcalls().get_mmtk_mutator)(tls);
+        unsafe { &mut *(m as *mut Mutator<OpenJDK<COMPRESSED>>) }
     }
 
     fn mutators<'a>() -> Box<dyn Iterator<Item = &'a mut Mutator<OpenJDK<COMPRESSED>>> + 'a> {
@@ -54,6 +50,6 @@ impl<const COMPRESSED: bool> ActivePlan<OpenJDK<COMPRESSED>> for VMActivePlan {
     }
 
     fn number_of_mutators() -> usize {
-        unsafe { ((*UPCALLS).number_of_mutators)() }
+        (upcalls().number_of_mutators)()
     }
 }
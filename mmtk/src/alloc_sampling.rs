@@ -0,0 +1,83 @@
+//! Allocation sampling, for JVMTI `SampledObjectAlloc` and allocation profilers.
+//!
+//! Each mutator counts the bytes it allocates.  Once it has allocated the sampling interval, the
+//! next object that goes through `post_alloc` is reported to the VM with the `sample_allocation`
+//! upcall.  The VM sets the interval, e.g. from the JVMTI heap sampling interval.
+//!
+//! Limitation: objects allocated in the fast path of the default allocator are not seen one by
+//! one.  We count them when the mutator retires its bump-pointer buffer (see `tlab.rs`), so an
+//! object allocated in the fast path is never sampled.  Instead, the sampled object is the first
+//! object allocated in the slow path after the interval is reached, which is biased towards
+//! objects that do not fit in the current buffer.
+
+use crate::upcalls;
+use mmtk::util::ObjectReference;
+use mmtk::vm::VMBinding;
+use mmtk::{AllocationSemantics, Mutator, MutatorContext};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The sampling interval in bytes, or 0 if sampling is disabled
+static SAMPLING_INTERVAL: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The bytes allocated by the mutator of the current thread since its last sample
+    static BYTES_SINCE_SAMPLE: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Set the sampling interval in bytes.  0 disables sampling.
+pub fn set_sampling_interval(bytes: usize) {
+    SAMPLING_INTERVAL.store(bytes, Ordering::Relaxed);
+}
+
+pub fn sampling_interval() -> usize {
+    SAMPLING_INTERVAL.load(Ordering::Relaxed)
+}
+
+/// Count bytes allocated by the current thread without going through `post_alloc`.
+pub(crate) fn count_allocated_bytes(bytes: usize) {
+    if sampling_interval() != 0 {
+        BYTES_SINCE_SAMPLE.with(|b| b.set(b.get().saturating_add(bytes)));
+    }
+}
+
+/// Called in `post_alloc`.  This is only a load and a branch if sampling is disabled.
+#[inline(always)]
+pub(crate) fn on_post_alloc<VM: VMBinding>(
+    mutator: &Mutator<VM>,
+    object: ObjectReference,
+    bytes: usize,
+    semantics: AllocationSemantics,
+) {
+    let interval = sampling_interval();
+    if interval != 0 {
+        sample_slow(mutator, object, bytes, semantics, interval);
+    }
+}
+
+#[cold]
+fn sample_slow<VM: VMBinding>(
+    mutator: &Mutator<VM>,
+    object: ObjectReference,
+    bytes: usize,
+    semantics: AllocationSemantics,
+    interval: usize,
+) {
+    let total = BYTES_SINCE_SAMPLE.with(|b| {
+        // Objects of the default allocator are counted by `tlab::alloc`.
+        let total = if matches!(semantics, AllocationSemantics::Default) {
+            b.get()
+        } else {
+            b.get().saturating_add(bytes)
+        };
+        b.set(if total >= interval {
+            total % interval
+        } else {
+            total
+        });
+        total
+    });
+    if total >= interval {
+        (upcalls().sample_allocation)(mutator.get_tls(), object, bytes);
+    }
+}
@@ -1,8 +1,12 @@
+use crate::gc_listener::GCListener;
+use crate::gc_stats::{GCCollectionCounts, GCStats, SpaceUsage};
+use crate::pretenure::AllocationSite;
 use crate::slots::OpenJDKSlot;
+use crate::tlab::TLAB;
+use crate::HeapObjectClosure;
 use crate::OpenJDK;
-use crate::OpenJDK_Upcalls;
 use crate::BUILDER;
-use crate::UPCALLS;
+use crate::{upcalls, OpenJDK_Upcalls};
 use libc::c_char;
 use mmtk::memory_manager;
 use mmtk::plan::BarrierSelector;
@@ -11,6 +15,7 @@ use mmtk::util::alloc::AllocatorSelector;
 use mmtk::util::api_util::NullableObjectReference;
 use mmtk::util::opaque_pointer::*;
 use mmtk::util::{Address, ObjectReference};
+use mmtk::vm::ObjectModel;
 use mmtk::AllocationSemantics;
 use mmtk::Mutator;
 use mmtk::MutatorContext;
@@ -77,8 +82,7 @@ pub unsafe extern "C" fn release_buffer(ptr: *mut Address, length: usize, capaci
 
 #[no_mangle]
 pub extern "C" fn openjdk_gc_init(calls: *const OpenJDK_Upcalls) {
-    unsafe { UPCALLS = calls };
-    crate::abi::validate_memory_layouts();
+    crate::upcalls::set_upcalls(calls);
 
     // We don't really need this, as we can dynamically set plans. However, for compatability of our CI scripts,
     // we allow selecting a plan using feature at build time.
@@ -119,6 +123,18 @@ pub extern "C" fn openjdk_gc_init(calls: *const OpenJDK_Upcalls) {
     } else {
         lazy_static::initialize(&crate::SINGLETON_UNCOMPRESSED);
     }
+    if crate::use_compressed_oops() {
+        crate::tlab::init::<true>();
+    } else {
+        crate::tlab::init::<false>();
+    }
+}
+
+/// Check that the structs in `abi.rs` match the memory layout of klasses in the VM.  This must be
+/// called after the well-known classes are loaded, because some checks read their klasses.
+#[no_mangle]
+pub extern "C" fn mmtk_validate_memory_layouts() {
+    crate::abi::validate_memory_layouts()
 }
 
 #[no_mangle]
@@ -127,10 +143,17 @@ pub extern "C" fn openjdk_is_gc_initialized() -> bool {
 }
 
 #[no_mangle]
-pub extern "C" fn mmtk_set_heap_size(min: usize, max: usize) -> bool {
+pub extern "C" fn mmtk_set_heap_size(min: usize, max: usize, resizable: bool) -> bool {
     use mmtk::util::options::GCTriggerSelector;
     let mut builder = BUILDER.lock().unwrap();
-    let policy = if min == max {
+    let policy = if resizable {
+        if max == 0 || min > max {
+            return false;
+        }
+        // We use our own trigger so that the heap size can be changed later.  See `gc_trigger.rs`.
+        crate::gc_trigger::set_initial_heap_size(min, max);
+        GCTriggerSelector::Delegated
+    } else if min == max {
         GCTriggerSelector::FixedHeapSize(min)
     } else {
         GCTriggerSelector::DynamicHeapSize(min, max)
@@ -138,6 +161,28 @@ pub extern "C" fn mmtk_set_heap_size(min: usize, max: usize) -> bool {
     builder.options.gc_trigger.set(policy)
 }
 
+/// Change the soft and hard limits of the heap size after MMTk is initialized.  Returns false if
+/// the heap is not resizable, or if the limits are invalid or do not fit in the address range
+/// reserved for the heap.
+#[no_mangle]
+pub extern "C" fn mmtk_set_heap_limits(soft_max: usize, max: usize) -> bool {
+    if !crate::MMTK_INITIALIZED.load(Ordering::SeqCst) {
+        return false;
+    }
+    let result = if crate::use_compressed_oops() {
+        crate::gc_trigger::set_heap_limits::<true>(soft_max, max)
+    } else {
+        crate::gc_trigger::set_heap_limits::<false>(soft_max, max)
+    };
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("{}", e);
+            false
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn bind_mutator(tls: VMMutatorThread) -> *mut libc::c_void {
     with_singleton!(|singleton| {
@@ -168,8 +213,47 @@ pub extern "C" fn alloc(
     align: usize,
     offset: usize,
     allocator: AllocationSemantics,
+    site: AllocationSite,
 ) -> Address {
-    with_mutator!(|mutator| memory_manager::alloc(mutator, size, align, offset, allocator))
+    let result =
+        with_mutator!(|mutator| crate::tlab::alloc(mutator, size, align, offset, allocator));
+    crate::pretenure::on_nursery_alloc(site, allocator, result);
+    result
+}
+
+/// Return the semantics to allocate an object of `site` with.  The VM calls this once per
+/// object, and passes the result to both `alloc` and `post_alloc`.
+#[no_mangle]
+pub extern "C" fn mmtk_select_allocation_semantics(
+    allocator: AllocationSemantics,
+    site: AllocationSite,
+) -> AllocationSemantics {
+    crate::pretenure::select_semantics(site, allocator)
+}
+
+#[no_mangle]
+// We trust the mutator pointer is valid.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn mmtk_get_tlab(mutator: *mut libc::c_void) -> TLAB {
+    with_mutator!(|mutator| crate::tlab::get_tlab(mutator))
+}
+
+/// Set the allocation sampling interval in bytes.  0 disables sampling.
+#[no_mangle]
+pub extern "C" fn mmtk_set_allocation_sampling_interval(bytes: usize) {
+    crate::alloc_sampling::set_sampling_interval(bytes)
+}
+
+/// Set the survival rate (in percent) above which allocation sites are pretenured.  0 disables
+/// pretenuring.
+#[no_mangle]
+pub extern "C" fn mmtk_set_pretenure_threshold(percent: usize) {
+    crate::pretenure::set_pretenure_threshold(percent)
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_set_soft_ref_lru_policy_ms_per_mb(ms_per_mb: usize) {
+    crate::soft_references::set_soft_ref_lru_policy_ms_per_mb(ms_per_mb)
 }
 
 #[no_mangle]
@@ -196,7 +280,10 @@ pub extern "C" fn post_alloc(
     bytes: usize,
     allocator: AllocationSemantics,
 ) {
-    with_mutator!(|mutator| memory_manager::post_alloc(mutator, refer, bytes, allocator))
+    with_mutator!(|mutator| {
+        memory_manager::post_alloc(mutator, refer, bytes, allocator);
+        crate::alloc_sampling::on_post_alloc(mutator, refer, bytes, allocator)
+    })
 }
 
 #[no_mangle]
@@ -244,6 +331,265 @@ pub extern "C" fn handle_user_collection_request(tls: VMMutatorThread) {
     })
 }
 
+/// Enumerate all objects in MMTk spaces, and report each of them to `closure`.
+///
+/// Objects that became unreachable since the last GC but have not been reclaimed yet may also
+/// be reported.  The caller must make sure no GC or allocation happens concurrently, for example,
+/// by calling this function in a safepoint.
+///
+/// Object enumeration relies on the valid-object (VO) bits.  Returns `false` without reporting
+/// any object if the binding is built without the `vo_bit` feature.
+#[no_mangle]
+pub extern "C" fn mmtk_iterate_objects(closure: HeapObjectClosure) -> bool {
+    #[cfg(feature = "vo_bit")]
+    {
+        with_singleton!(|singleton| {
+            memory_manager::enumerate_objects(singleton, |object| {
+                (closure.func)(object, closure.data)
+            });
+        });
+        true
+    }
+    #[cfg(not(feature = "vo_bit"))]
+    {
+        let _ = closure;
+        false
+    }
+}
+
+/// Invalidate the scan plans that GC threads cache for each klass.  The VM must call this after
+/// unloading classes, before the next GC.
+#[no_mangle]
+pub extern "C" fn mmtk_invalidate_scan_plans() {
+    crate::object_scanning::invalidate_scan_plans()
+}
+
+/// Return the start of the object that contains the interior pointer `addr`, or null if `addr` is
+/// not inside a live object.  `addr` does not need to be in MMTk spaces, and a pointer to the end
+/// of an object is not inside that object.  Outside the large object space, only objects that start
+/// at most `max_search_bytes` before `addr` are found.  In the large object space, objects are
+/// always found, and the search takes time linear in the number of pages between `addr` and the
+/// start of the object.  This can resolve pointers from native code, or derived pointers, to their
+/// objects.
+///
+/// This relies on the valid-object (VO) bits, and always returns null if the binding is built
+/// without the `vo_bit` feature.  The caller must make sure no GC is in progress.
+#[no_mangle]
+pub extern "C" fn mmtk_find_object_from_interior_pointer(
+    addr: Address,
+    max_search_bytes: usize,
+) -> NullableObjectReference {
+    #[cfg(feature = "vo_bit")]
+    {
+        fn find_object<const COMPRESSED: bool>(
+            addr: Address,
+            max_search_bytes: usize,
+        ) -> Option<ObjectReference> {
+            use mmtk::plan::HasSpaces;
+            use mmtk::policy::space::Space;
+            // The large object space finds the start of an object by checking the VO bit of each
+            // page, and stops at the first one it finds.  So we can search as far back as needed.
+            let mut in_los = false;
+            crate::singleton::<COMPRESSED>()
+                .get_plan()
+                .for_each_space(&mut |space| {
+                    in_los |= space.get_name() == "los" && space.address_in_space(addr);
+                });
+            let max_search_bytes = if in_los {
+                addr.as_usize()
+            } else {
+                max_search_bytes
+            };
+            memory_manager::find_object_from_internal_pointer::<OpenJDK<COMPRESSED>>(
+                addr,
+                max_search_bytes,
+            )
+        }
+        if addr.is_zero() {
+            return Option::<ObjectReference>::None.into();
+        }
+        if crate::use_compressed_oops() {
+            find_object::<true>(addr, max_search_bytes).into()
+        } else {
+            find_object::<false>(addr, max_search_bytes).into()
+        }
+    }
+    #[cfg(not(feature = "vo_bit"))]
+    {
+        let _ = (addr, max_search_bytes);
+        Option::<ObjectReference>::None.into()
+    }
+}
+
+/// Return the start of the object that contains `addr`, or null if `addr` is not inside a live
+/// object.  This implements `CollectedHeap::block_start` with
+/// `mmtk_find_object_from_interior_pointer`.  Objects larger than the maximum non-LOS allocation
+/// size are in the large object space, so we never need to search further back than that.
+#[no_mangle]
+pub extern "C" fn mmtk_block_start(addr: Address) -> NullableObjectReference {
+    let max_search_bytes = with_singleton!(|singleton| {
+        singleton
+            .get_plan()
+            .constraints()
+            .max_non_los_default_alloc_bytes
+    });
+    mmtk_find_object_from_interior_pointer(addr, max_search_bytes)
+}
+
+/// Return true if `addr` is the start of an object.  This only reads the VO bit of `addr`, and
+/// always returns false if the binding is built without the `vo_bit` feature.
+#[no_mangle]
+pub extern "C" fn mmtk_is_object_start(addr: Address) -> bool {
+    #[cfg(feature = "vo_bit")]
+    {
+        !addr.is_zero()
+            && addr.is_aligned_to(ObjectReference::ALIGNMENT)
+            && memory_manager::is_mmtk_object(addr).is_some()
+    }
+    #[cfg(not(feature = "vo_bit"))]
+    {
+        let _ = addr;
+        false
+    }
+}
+
+/// Return the size of `object` in bytes.
+#[no_mangle]
+pub extern "C" fn mmtk_get_object_size(object: ObjectReference) -> usize {
+    if crate::use_compressed_oops() {
+        crate::object_model::VMObjectModel::<true>::get_current_size(object)
+    } else {
+        crate::object_model::VMObjectModel::<false>::get_current_size(object)
+    }
+}
+
+/// Write a heap dump in the HPROF format to the file at `path`.  Returns `false` if the dump
+/// cannot be written.
+///
+/// All mutators must be stopped, for example, by calling this function in a safepoint.  Like
+/// `mmtk_iterate_objects`, this needs the `vo_bit` feature.
+#[no_mangle]
+// We trust the path pointer is valid.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn mmtk_dump_heap(path: *const c_char) -> bool {
+    let path_str: &CStr = unsafe { CStr::from_ptr(path) };
+    let path = path_str.to_str().unwrap();
+    let result = if crate::use_compressed_oops() {
+        crate::heap_dump::dump_heap::<true>(path)
+    } else {
+        crate::heap_dump::dump_heap::<false>(path)
+    };
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("Failed to write heap dump to {}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Verify all objects reachable from roots, and log the bad references we find.  Returns the
+/// number of bad references.  All mutators must be stopped.
+#[no_mangle]
+pub extern "C" fn mmtk_verify_heap() -> usize {
+    if crate::use_compressed_oops() {
+        crate::verify::verify_heap::<true>()
+    } else {
+        crate::verify::verify_heap::<false>()
+    }
+}
+
+/// Copy the statistics of up to `max` most recent GCs into `buf`, oldest first.
+/// Returns the number of records copied.
+#[no_mangle]
+// We trust the buffer pointer is valid and has room for `max` records.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn mmtk_get_gc_stats(buf: *mut GCStats, max: usize) -> usize {
+    if buf.is_null() || max == 0 {
+        return 0;
+    }
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf, max) };
+    crate::gc_stats::copy_recent_gc_stats(buf)
+}
+
+/// Copy the usage of up to `max` spaces of the current plan into `buf`.
+/// Returns the number of spaces, which may be larger than `max`.
+#[no_mangle]
+// We trust the buffer pointer is valid and has room for `max` records.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn mmtk_get_space_usage(buf: *mut SpaceUsage, max: usize) -> usize {
+    let buf: &mut [SpaceUsage] = if buf.is_null() {
+        &mut []
+    } else {
+        unsafe { std::slice::from_raw_parts_mut(buf, max) }
+    };
+    if crate::use_compressed_oops() {
+        crate::gc_stats::copy_space_usage::<true>(buf)
+    } else {
+        crate::gc_stats::copy_space_usage::<false>(buf)
+    }
+}
+
+/// Set the idle interval of periodic GC in milliseconds.  0 disables periodic GC.
+#[no_mangle]
+pub extern "C" fn mmtk_set_periodic_gc_interval(interval_ms: u64) {
+    crate::periodic_gc::set_periodic_gc_interval(interval_ms)
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_periodic_gc_interval() -> u64 {
+    crate::periodic_gc::periodic_gc_interval()
+}
+
+/// Perform a periodic GC if one is due.  Returns the time in milliseconds until the next poll, or
+/// 0 if periodic GC is disabled.  `tls` must be a mutator thread that can block for GC.
+#[no_mangle]
+pub extern "C" fn mmtk_periodic_gc_poll(tls: VMMutatorThread) -> u64 {
+    if crate::use_compressed_oops() {
+        crate::periodic_gc::poll::<true>(tls)
+    } else {
+        crate::periodic_gc::poll::<false>(tls)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_get_collection_counts() -> GCCollectionCounts {
+    crate::gc_stats::collection_counts()
+}
+
+/// Register a listener that is called at the end of every GC pause, before the mutators are
+/// resumed.  The listener runs in a GC worker thread.
+#[no_mangle]
+pub extern "C" fn mmtk_add_gc_listener(listener: GCListener) {
+    crate::gc_listener::add_gc_listener(listener)
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_remove_gc_listener(listener: GCListener) {
+    crate::gc_listener::remove_gc_listener(listener)
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_is_generational_plan() -> bool {
+    with_singleton!(|singleton| singleton.get_plan().generational().is_some())
+}
+
+/// Return true if the current (or the last) GC is a nursery GC.
+#[no_mangle]
+pub extern "C" fn mmtk_is_current_gc_nursery() -> bool {
+    with_singleton!(|singleton| singleton
+        .get_plan()
+        .generational()
+        .is_some_and(|gen| gen.is_current_gc_nursery()))
+}
+
+/// Set the object alignment to HotSpot's `ObjectAlignmentInBytes`.  Must be called before
+/// `openjdk_gc_init`.
+#[no_mangle]
+pub extern "C" fn mmtk_set_object_alignment(bytes: usize) {
+    crate::object_model::set_object_alignment(bytes)
+}
+
 #[no_mangle]
 pub extern "C" fn mmtk_enable_compressed_oops() {
     crate::slots::enable_compressed_oops()
@@ -287,7 +633,7 @@ pub extern "C" fn add_phantom_candidate(reff: ObjectReference) {
 
 #[no_mangle]
 pub extern "C" fn harness_begin(_id: usize) {
-    unsafe { ((*UPCALLS).harness_begin)() };
+    (upcalls().harness_begin)();
 }
 
 #[no_mangle]
@@ -300,7 +646,7 @@ pub extern "C" fn mmtk_harness_begin_impl() {
 
 #[no_mangle]
 pub extern "C" fn harness_end(_id: usize) {
-    unsafe { ((*UPCALLS).harness_end)() };
+    (upcalls().harness_end)();
 }
 
 #[no_mangle]
@@ -480,14 +826,14 @@ pub extern "C" fn mmtk_object_probable_write(mutator: *mut libc::c_void, obj: Ob
 }
 
 // finalization
-#[no_mangle]
-pub extern "C" fn add_finalizer(object: ObjectReference) {
-    with_singleton!(|singleton| memory_manager::add_finalizer(singleton, object));
-}
 
+/// Get a `FinalReference` whose referent is ready for finalization, or null if there is none.
+/// The caller should put it into the reference pending list.
 #[no_mangle]
 pub extern "C" fn get_finalized_object() -> NullableObjectReference {
-    with_singleton!(|singleton| memory_manager::get_finalized_object(singleton).into())
+    with_singleton!(|singleton| memory_manager::get_finalized_object(singleton)
+        .map(|final_reference| final_reference.reference())
+        .into())
 }
 
 thread_local! {
@@ -1,9 +1,10 @@
 use mmtk::util::alloc::AllocationError;
+use mmtk::util::heap::gc_trigger::GCTriggerPolicy;
 use mmtk::util::opaque_pointer::*;
 use mmtk::vm::{Collection, GCThreadContext};
 use mmtk::Mutator;
 
-use crate::{singleton, UPCALLS};
+use crate::{singleton, upcalls};
 use crate::{MutatorClosure, OpenJDK};
 
 pub struct VMCollection {}
@@ -15,12 +16,13 @@ impl<const COMPRESSED: bool> Collection<OpenJDK<COMPRESSED>> for VMCollection {
     where
         F: FnMut(&'static mut Mutator<OpenJDK<COMPRESSED>>),
     {
-        unsafe {
-            ((*UPCALLS).stop_all_mutators)(
-                tls,
-                MutatorClosure::from_rust_closure::<_, COMPRESSED>(&mut mutator_visitor),
-            );
-        }
+        crate::gc_stats::on_stop_all_mutators::<COMPRESSED>();
+        (upcalls().stop_all_mutators)(
+            tls,
+            MutatorClosure::from_rust_closure::<_, COMPRESSED>(&mut mutator_visitor),
+        );
+        crate::gc_stats::on_all_mutators_stopped();
+        crate::soft_references::setup_policy::<COMPRESSED>();
     }
 
     fn resume_mutators(tls: VMWorkerThread) {
@@ -36,15 +38,17 @@ impl<const COMPRESSED: bool> Collection<OpenJDK<COMPRESSED>> for VMCollection {
             }
             log::debug!("Set CONCURRENT_MARKING_ACTIVE to {concurrent_marking_active}");
         }
-        unsafe {
-            ((*UPCALLS).resume_mutators)(tls);
+        crate::periodic_gc::on_resume_mutators::<COMPRESSED>();
+        if let Some(event) = crate::gc_stats::on_resume_mutators::<COMPRESSED>() {
+            crate::gc_listener::notify_gc_listeners(&event);
         }
+        crate::soft_references::end_gc::<COMPRESSED>();
+        (upcalls().resume_mutators)(tls);
+        crate::gc_stats::on_mutators_resumed();
     }
 
     fn block_for_gc(_tls: VMMutatorThread) {
-        unsafe {
-            ((*UPCALLS).block_for_gc)();
-        }
+        (upcalls().block_for_gc)();
     }
 
     fn spawn_gc_thread(tls: VMThread, ctx: GCThreadContext<OpenJDK<COMPRESSED>>) {
@@ -53,20 +57,18 @@ impl<const COMPRESSED: bool> Collection<OpenJDK<COMPRESSED>> for VMCollection {
                 (Box::into_raw(w) as *mut libc::c_void, GC_THREAD_KIND_WORKER)
             }
         };
-        unsafe {
-            ((*UPCALLS).spawn_gc_thread)(tls, kind, ctx_ptr);
-        }
+        (upcalls().spawn_gc_thread)(tls, kind, ctx_ptr);
     }
 
     fn out_of_memory(tls: VMThread, err_kind: AllocationError) {
-        unsafe {
-            ((*UPCALLS).out_of_memory)(tls, err_kind);
-        }
+        (upcalls().out_of_memory)(tls, err_kind);
     }
 
     fn schedule_finalization(_tls: VMWorkerThread) {
-        unsafe {
-            ((*UPCALLS).schedule_finalizer)();
-        }
+        (upcalls().schedule_finalizer)();
+    }
+
+    fn create_gc_trigger() -> Box<dyn GCTriggerPolicy<OpenJDK<COMPRESSED>>> {
+        Box::new(crate::gc_trigger::OpenJDKGCTrigger)
     }
 }
@@ -0,0 +1,70 @@
+//! Listeners that are notified at the end of every GC pause, before the mutators are resumed.
+//! The VM uses them to emit `GarbageCollectionNotificationInfo` events.
+
+use crate::gc_stats::{GCCause, GCKind, GCStats, SpaceUsage};
+use std::sync::Mutex;
+
+/// The event passed to GC listeners.  The usage arrays have `num_spaces` elements each, in the
+/// same order as `mmtk_get_space_usage`, and are only valid during the call to the listener.
+#[repr(C)]
+pub struct GCEvent {
+    pub gc_id: u64,
+    pub kind: GCKind,
+    pub cause: GCCause,
+    /// The start of the pause, comparable with `os::javaTimeNanos()`
+    pub pause_start: u64,
+    /// The duration of the pause so far, in nanoseconds
+    pub duration: u64,
+    pub num_spaces: usize,
+    pub usage_before: *const SpaceUsage,
+    pub usage_after: *const SpaceUsage,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct GCListener {
+    pub func: extern "C" fn(event: *const GCEvent, data: *mut libc::c_void),
+    pub data: *mut libc::c_void,
+}
+
+// Note on `unsafe`: The VM is responsible for making `data` usable from GC worker threads.
+unsafe impl Send for GCListener {}
+
+/// The data of a finished pause, owned by the binding while the listeners are called.
+pub(crate) struct GCEventData {
+    pub stats: GCStats,
+    pub duration: u64,
+    pub usage_before: Vec<SpaceUsage>,
+    pub usage_after: Vec<SpaceUsage>,
+}
+
+lazy_static! {
+    static ref GC_LISTENERS: Mutex<Vec<GCListener>> = Mutex::new(vec![]);
+}
+
+pub fn add_gc_listener(listener: GCListener) {
+    GC_LISTENERS.lock().unwrap().push(listener);
+}
+
+pub fn remove_gc_listener(listener: GCListener) {
+    GC_LISTENERS.lock().unwrap().retain(|l| *l != listener);
+}
+
+pub(crate) fn notify_gc_listeners(data: &GCEventData) {
+    debug_assert_eq!(data.usage_before.len(), data.usage_after.len());
+    let event = GCEvent {
+        gc_id: data.stats.gc_id,
+        kind: data.stats.kind,
+        cause: data.stats.cause,
+        pause_start: data.stats.pause_start,
+        duration: data.duration,
+        num_spaces: data.usage_before.len().min(data.usage_after.len()),
+        usage_before: data.usage_before.as_ptr(),
+        usage_after: data.usage_after.as_ptr(),
+    };
+    // Copy the listeners so that a listener can (un)register listeners without deadlocking.
+    let listeners = GC_LISTENERS.lock().unwrap().clone();
+    for listener in listeners {
+        (listener.func)(&event, listener.data);
+    }
+}
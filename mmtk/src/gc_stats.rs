@@ -0,0 +1,338 @@
+//! Statistics of recent GCs and the usage of spaces, exported to the VM by `mmtk_get_gc_stats`,
+//! `mmtk_get_collection_counts` and `mmtk_get_space_usage`.
+//!
+//! A GC pause begins when MMTk asks us to stop all mutators, and ends when the mutators are
+//! resumed.  Counters that are updated during the pause (root scanning time and reference counts)
+//! are kept in atomics, and are moved into the record when the pause ends.
+
+use crate::gc_listener::GCEventData;
+use crate::singleton;
+use libc::c_char;
+use mmtk::memory_manager;
+use mmtk::plan::HasSpaces;
+use mmtk::policy::space::Space;
+use mmtk::util::conversions;
+use mmtk::util::heap::PageResource;
+use once_cell::sync::OnceCell;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CString;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The number of GCs we keep statistics for.
+pub const GC_STATS_HISTORY: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GCKind {
+    Nursery = 0,
+    Full = 1,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GCCause {
+    /// The GC is triggered by MMTk, usually because the heap is full.
+    HeapFull = 0,
+    /// The GC is requested by the VM or the application, e.g. by `System.gc()`.
+    UserRequested = 1,
+    /// The GC is requested by the binding after the VM has been idle.  See `periodic_gc.rs`.
+    Periodic = 2,
+}
+
+/// Statistics of one GC pause.  Times are in nanoseconds.  Timestamps are taken from the same
+/// monotonic clock as HotSpot's `os::javaTimeNanos()`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GCStats {
+    /// The sequence number of this GC, starting from 1
+    pub gc_id: u64,
+    pub kind: GCKind,
+    /// The name of the plan, as a static NUL-terminated string
+    pub plan: *const c_char,
+    pub pause_start: u64,
+    pub pause_end: u64,
+    pub used_bytes_before: usize,
+    pub used_bytes_after: usize,
+    /// Time spent stopping all mutators
+    pub stop_mutators_time: u64,
+    /// Time GC workers spent scanning roots, summed over all workers
+    pub root_scanning_time: u64,
+    /// The number of soft, weak and phantom references discovered during this GC
+    pub references_discovered: usize,
+    /// The number of references added to the reference pending list during this GC
+    pub references_enqueued: usize,
+    /// The number of `FinalReference` instances registered for finalization during this GC
+    pub finalizers_registered: usize,
+    pub cause: GCCause,
+    /// The decrease in the bytes that the page resources of all spaces count as committed.  Pages
+    /// freed by a space may stay mapped, so this is not necessarily memory returned to the OS.
+    pub committed_bytes_decrease: usize,
+    /// The decrease in the resident set size when the free chunks were released to the OS.  Only
+    /// periodic GCs release memory.  See `periodic_gc.rs`.
+    pub released_bytes: usize,
+}
+
+// Note on `unsafe`: The only pointer in `GCStats` points to a static string.
+unsafe impl Send for GCStats {}
+
+/// The number and total pause time (in nanoseconds) of nursery and full-heap GCs.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GCCollectionCounts {
+    pub nursery_count: u64,
+    pub nursery_time: u64,
+    pub full_count: u64,
+    pub full_time: u64,
+}
+
+/// The usage of one MMTk space, in bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SpaceUsage {
+    /// The name of the space, as a static NUL-terminated string
+    pub name: *const c_char,
+    /// The size of the address range reserved for the space, or 0 if the space is discontiguous
+    /// and shares the address range of the heap with other spaces
+    pub reserved: usize,
+    pub committed: usize,
+    pub used: usize,
+}
+
+// Note on `unsafe`: The only pointer in `SpaceUsage` points to a static string.
+unsafe impl Send for SpaceUsage {}
+
+struct GCStatsState {
+    /// The number of GC pauses started so far
+    count: u64,
+    counts: GCCollectionCounts,
+    /// The pause in progress
+    current: Option<GCStats>,
+    /// The usage of each space when the pause in progress started
+    usage_before: Vec<SpaceUsage>,
+    /// The most recent pauses, oldest first
+    history: VecDeque<GCStats>,
+}
+
+lazy_static! {
+    static ref GC_STATS: Mutex<GCStatsState> = Mutex::new(GCStatsState {
+        count: 0,
+        counts: GCCollectionCounts::default(),
+        current: None,
+        usage_before: vec![],
+        history: VecDeque::with_capacity(GC_STATS_HISTORY),
+    });
+}
+
+static ROOT_SCANNING_TIME: AtomicU64 = AtomicU64::new(0);
+static REFERENCES_DISCOVERED: AtomicUsize = AtomicUsize::new(0);
+static REFERENCES_ENQUEUED: AtomicUsize = AtomicUsize::new(0);
+static FINALIZERS_REGISTERED: AtomicUsize = AtomicUsize::new(0);
+static RELEASED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The current time of the monotonic clock, in nanoseconds
+pub fn nanos_now() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let ret = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    debug_assert_eq!(ret, 0);
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+fn plan_name<const COMPRESSED: bool>() -> *const c_char {
+    static PLAN_NAME: OnceCell<CString> = OnceCell::new();
+    PLAN_NAME
+        .get_or_init(|| {
+            let plan = *singleton::<COMPRESSED>().get_options().plan;
+            CString::new(format!("{:?}", plan)).unwrap()
+        })
+        .as_ptr()
+}
+
+/// Called before we request the VM to stop all mutators.
+pub(crate) fn on_stop_all_mutators<const COMPRESSED: bool>() {
+    let mmtk = singleton::<COMPRESSED>();
+    let cause = if crate::periodic_gc::take_periodic_gc_request() {
+        GCCause::Periodic
+    } else if mmtk.is_user_triggered_collection() {
+        GCCause::UserRequested
+    } else {
+        GCCause::HeapFull
+    };
+    let mut usage_before = vec![];
+    for_each_space_usage::<COMPRESSED>(|usage| usage_before.push(usage));
+    let mut state = GC_STATS.lock().unwrap();
+    state.usage_before = usage_before;
+    state.count += 1;
+    state.current = Some(GCStats {
+        gc_id: state.count,
+        kind: GCKind::Full,
+        plan: plan_name::<COMPRESSED>(),
+        pause_start: nanos_now(),
+        pause_end: 0,
+        used_bytes_before: memory_manager::used_bytes(mmtk),
+        used_bytes_after: 0,
+        stop_mutators_time: 0,
+        root_scanning_time: 0,
+        references_discovered: 0,
+        references_enqueued: 0,
+        finalizers_registered: 0,
+        cause,
+        committed_bytes_decrease: 0,
+        released_bytes: 0,
+    });
+}
+
+/// Called after all mutators are stopped.
+pub(crate) fn on_all_mutators_stopped() {
+    let mut state = GC_STATS.lock().unwrap();
+    if let Some(current) = state.current.as_mut() {
+        current.stop_mutators_time = nanos_now() - current.pause_start;
+    }
+}
+
+/// Called before we request the VM to resume all mutators.  Returns the event to be reported to
+/// GC listeners, or `None` if we have not seen the start of this pause.
+pub(crate) fn on_resume_mutators<const COMPRESSED: bool>() -> Option<GCEventData> {
+    let mmtk = singleton::<COMPRESSED>();
+    let is_nursery = mmtk
+        .get_plan()
+        .generational()
+        .is_some_and(|gen| gen.is_current_gc_nursery());
+    let mut usage_after = vec![];
+    for_each_space_usage::<COMPRESSED>(|usage| usage_after.push(usage));
+    let mut state = GC_STATS.lock().unwrap();
+    let usage_before = std::mem::take(&mut state.usage_before);
+    state.current.as_mut().map(|current| {
+        current.kind = if is_nursery {
+            GCKind::Nursery
+        } else {
+            GCKind::Full
+        };
+        current.used_bytes_after = memory_manager::used_bytes(mmtk);
+        current.root_scanning_time = ROOT_SCANNING_TIME.swap(0, Ordering::Relaxed);
+        current.references_discovered = REFERENCES_DISCOVERED.swap(0, Ordering::Relaxed);
+        current.references_enqueued = REFERENCES_ENQUEUED.swap(0, Ordering::Relaxed);
+        current.finalizers_registered = FINALIZERS_REGISTERED.swap(0, Ordering::Relaxed);
+        current.released_bytes = RELEASED_BYTES.swap(0, Ordering::Relaxed);
+        let committed_before: usize = usage_before.iter().map(|usage| usage.committed).sum();
+        let committed_after: usize = usage_after.iter().map(|usage| usage.committed).sum();
+        current.committed_bytes_decrease = committed_before.saturating_sub(committed_after);
+        GCEventData {
+            stats: *current,
+            duration: nanos_now() - current.pause_start,
+            usage_before,
+            usage_after,
+        }
+    })
+}
+
+/// Called after all mutators are resumed.  This finishes the record of the current pause.
+pub(crate) fn on_mutators_resumed() {
+    let mut state = GC_STATS.lock().unwrap();
+    if let Some(mut current) = state.current.take() {
+        current.pause_end = nanos_now();
+        let pause_time = current.pause_end - current.pause_start;
+        match current.kind {
+            GCKind::Nursery => {
+                state.counts.nursery_count += 1;
+                state.counts.nursery_time += pause_time;
+            }
+            GCKind::Full => {
+                state.counts.full_count += 1;
+                state.counts.full_time += pause_time;
+            }
+        }
+        if state.history.len() == GC_STATS_HISTORY {
+            state.history.pop_front();
+        }
+        state.history.push_back(current);
+    }
+}
+
+/// Run `f` and account the time spent as root scanning time.
+pub(crate) fn time_root_scanning<R>(f: impl FnOnce() -> R) -> R {
+    let start = nanos_now();
+    let result = f();
+    ROOT_SCANNING_TIME.fetch_add(nanos_now() - start, Ordering::Relaxed);
+    result
+}
+
+pub(crate) fn count_discovered_reference() {
+    REFERENCES_DISCOVERED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn count_enqueued_references(count: usize) {
+    REFERENCES_ENQUEUED.fetch_add(count, Ordering::Relaxed);
+}
+
+pub(crate) fn count_registered_finalizer() {
+    FINALIZERS_REGISTERED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn set_released_bytes(bytes: usize) {
+    RELEASED_BYTES.store(bytes, Ordering::Relaxed);
+}
+
+/// Copy the records of the most recent GCs into `buf`, oldest first.  Returns the number of
+/// records copied.
+pub fn copy_recent_gc_stats(buf: &mut [GCStats]) -> usize {
+    let state = GC_STATS.lock().unwrap();
+    let n = buf.len().min(state.history.len());
+    let skipped = state.history.len() - n;
+    for (dst, src) in buf.iter_mut().zip(state.history.iter().skip(skipped)) {
+        *dst = *src;
+    }
+    n
+}
+
+/// The record of the most recent GC, if any.
+pub(crate) fn last_gc_stats() -> Option<GCStats> {
+    GC_STATS.lock().unwrap().history.back().copied()
+}
+
+pub fn collection_counts() -> GCCollectionCounts {
+    GC_STATS.lock().unwrap().counts
+}
+
+/// Space names are `&'static str` in MMTk.  We keep a NUL-terminated copy of each name for the VM.
+fn space_name(name: &'static str) -> *const c_char {
+    lazy_static! {
+        static ref SPACE_NAMES: Mutex<HashMap<&'static str, CString>> = Mutex::new(HashMap::new());
+    }
+    let mut names = SPACE_NAMES.lock().unwrap();
+    names
+        .entry(name)
+        .or_insert_with(|| CString::new(name).unwrap())
+        .as_ptr()
+}
+
+/// Call `f` with the usage of each space of the current plan.
+pub fn for_each_space_usage<const COMPRESSED: bool>(mut f: impl FnMut(SpaceUsage)) {
+    singleton::<COMPRESSED>()
+        .get_plan()
+        .for_each_space(&mut |space| {
+            let common = space.common();
+            f(SpaceUsage {
+                name: space_name(space.get_name()),
+                reserved: if common.contiguous { common.extent } else { 0 },
+                committed: conversions::pages_to_bytes(space.get_page_resource().committed_pages()),
+                used: conversions::pages_to_bytes(space.reserved_pages()),
+            })
+        });
+}
+
+/// Copy the usage of the spaces of the current plan into `buf`.  Returns the number of spaces,
+/// which may be larger than `buf.len()`.
+pub fn copy_space_usage<const COMPRESSED: bool>(buf: &mut [SpaceUsage]) -> usize {
+    let mut n = 0;
+    for_each_space_usage::<COMPRESSED>(|usage| {
+        if let Some(slot) = buf.get_mut(n) {
+            *slot = usage;
+        }
+        n += 1;
+    });
+    n
+}
@@ -0,0 +1,155 @@
+//! The GC trigger of the binding for resizable heaps.  Unlike the built-in triggers of MMTk, its
+//! heap size limits can be changed after MMTk is initialized, with `mmtk_set_heap_limits`.  It is
+//! only used if the VM asks for a resizable heap in `mmtk_set_heap_size`.  Otherwise, we use
+//! `FixedHeapSize` or `DynamicHeapSize`.
+//!
+//! There are three limits:
+//! -   `min`: the initial heap size.  The heap does not shrink below it (or `soft_max`).
+//! -   `soft_max`: the heap size we try to stay within.  The heap grows beyond it (up to `max`)
+//!     only if the live data after a GC does not fit.
+//! -   `max`: the hard limit.  We run out of memory if the live data does not fit.
+//!
+//! After each GC, the heap size is set to twice the size of the live data, within these limits.
+//! If `min == soft_max == max`, this is the same as `FixedHeapSize`.
+
+use crate::OpenJDK;
+use mmtk::plan::Plan;
+use mmtk::util::conversions;
+use mmtk::util::heap::gc_trigger::{GCTriggerPolicy, SpaceStats};
+use mmtk::util::heap::vm_layout::vm_layout;
+use mmtk::util::options::GCTriggerSelector;
+use mmtk::MMTK;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Heap size limits, in pages.
+#[derive(Clone, Copy, Debug)]
+struct HeapLimits {
+    min: usize,
+    soft_max: usize,
+    max: usize,
+}
+
+static HEAP_LIMITS: Mutex<HeapLimits> = Mutex::new(HeapLimits {
+    min: 0,
+    soft_max: 0,
+    max: 0,
+});
+
+/// The number of pages reserved after the last GC
+static LIVE_PAGES: AtomicUsize = AtomicUsize::new(0);
+/// The current heap size.  A GC is triggered if the reserved pages exceed it.
+static CURRENT_HEAP_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+impl HeapLimits {
+    fn heap_pages_for(&self, live: usize) -> usize {
+        let target = live.saturating_mul(2);
+        if live < self.soft_max {
+            target.clamp(self.min.min(self.soft_max), self.soft_max)
+        } else {
+            target.min(self.max)
+        }
+    }
+}
+
+fn update_current_heap_pages(limits: &HeapLimits) {
+    let pages = limits.heap_pages_for(LIVE_PAGES.load(Ordering::Relaxed));
+    CURRENT_HEAP_PAGES.store(pages, Ordering::Relaxed);
+}
+
+/// Set the initial limits before MMTk is initialized.  `soft_max` is the same as `max`.
+pub(crate) fn set_initial_heap_size(min: usize, max: usize) {
+    let limits = HeapLimits {
+        min: conversions::bytes_to_pages_up(min),
+        soft_max: conversions::bytes_to_pages_up(max),
+        max: conversions::bytes_to_pages_up(max),
+    };
+    *HEAP_LIMITS.lock().unwrap() = limits;
+    update_current_heap_pages(&limits);
+}
+
+/// The maximum heap size in bytes, for choosing the VM layout.
+pub(crate) fn max_heap_size(selector: &GCTriggerSelector) -> usize {
+    match selector {
+        GCTriggerSelector::Delegated => {
+            conversions::pages_to_bytes(HEAP_LIMITS.lock().unwrap().max)
+        }
+        _ => selector.max_heap_size(),
+    }
+}
+
+/// Change the soft and hard limits of the heap size after MMTk is initialized.  The hard limit
+/// must fit in the address range reserved for the heap.  With compressed oops, this range also
+/// bounds the heap size that the narrow oop encoding chosen at start-up can address.
+pub fn set_heap_limits<const COMPRESSED: bool>(soft_max: usize, max: usize) -> Result<(), String> {
+    let trigger = &*crate::singleton::<COMPRESSED>().get_options().gc_trigger;
+    if !matches!(trigger, GCTriggerSelector::Delegated) {
+        return Err(format!(
+            "The heap size cannot be changed with the GC trigger {:?}",
+            trigger
+        ));
+    }
+    if max == 0 || soft_max > max {
+        return Err(format!(
+            "Invalid heap limits: soft max = {}, max = {}",
+            soft_max, max
+        ));
+    }
+    let layout = vm_layout();
+    let reserved = layout.heap_end - layout.heap_start;
+    if max > reserved {
+        return Err(format!(
+            "The max heap size {} is larger than the {} bytes reserved for the heap{}",
+            max,
+            reserved,
+            if COMPRESSED {
+                " with compressed oops"
+            } else {
+                ""
+            }
+        ));
+    }
+
+    let mut limits = HEAP_LIMITS.lock().unwrap();
+    limits.soft_max = conversions::bytes_to_pages_up(soft_max);
+    limits.max = conversions::bytes_to_pages_up(max);
+    update_current_heap_pages(&limits);
+    Ok(())
+}
+
+/// Created by MMTk for `GCTriggerSelector::Delegated`.  The state is kept in statics, so that the
+/// limits can be changed without access to the trigger object owned by MMTk.
+pub struct OpenJDKGCTrigger;
+
+impl<const COMPRESSED: bool> GCTriggerPolicy<OpenJDK<COMPRESSED>> for OpenJDKGCTrigger {
+    fn on_gc_end(&self, mmtk: &'static MMTK<OpenJDK<COMPRESSED>>) {
+        LIVE_PAGES.store(mmtk.get_plan().get_reserved_pages(), Ordering::Relaxed);
+        update_current_heap_pages(&HEAP_LIMITS.lock().unwrap());
+    }
+
+    fn is_gc_required(
+        &self,
+        space_full: bool,
+        space: Option<SpaceStats<OpenJDK<COMPRESSED>>>,
+        plan: &dyn Plan<VM = OpenJDK<COMPRESSED>>,
+    ) -> bool {
+        // Let the plan decide.  It compares the reserved pages with the current heap size.
+        plan.collection_required(space_full, space)
+    }
+
+    fn is_heap_full(&self, plan: &dyn Plan<VM = OpenJDK<COMPRESSED>>) -> bool {
+        plan.get_reserved_pages() > CURRENT_HEAP_PAGES.load(Ordering::Relaxed)
+    }
+
+    fn get_current_heap_size_in_pages(&self) -> usize {
+        CURRENT_HEAP_PAGES.load(Ordering::Relaxed)
+    }
+
+    fn get_max_heap_size_in_pages(&self) -> usize {
+        HEAP_LIMITS.lock().unwrap().max
+    }
+
+    fn can_heap_size_grow(&self) -> bool {
+        CURRENT_HEAP_PAGES.load(Ordering::Relaxed) < HEAP_LIMITS.lock().unwrap().max
+    }
+}
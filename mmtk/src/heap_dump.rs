@@ -0,0 +1,539 @@
+//! Heap dumps in the HPROF binary format.
+//!
+//! The format is the one written by HotSpot's `HeapDumper` (see `services/heapDumper.cpp` in
+//! OpenJDK), so the dumps can be read by the usual tools such as Eclipse MAT and VisualVM.
+//! We enumerate objects using the VO bits, read object layouts through the mirror structs in
+//! `abi.rs`, and report GC roots using the same upcalls as root scanning.
+//!
+//! Heap dumps must be written while all mutators are stopped.
+
+use crate::abi::*;
+use crate::roots::{for_each_root_category, RootCategory};
+use crate::{upcalls, FieldClosure, HeapObjectClosure, OpenJDKSlot};
+use mmtk::util::{Address, ObjectReference};
+use mmtk::vm::slot::{MemorySlice, Slot};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HPROF_HEADER: &[u8] = b"JAVA PROFILE 1.0.2\0";
+/// We always use 64-bit identifiers, and write compressed references in their decompressed form.
+const ID_SIZE: u32 = 8;
+/// Start a new heap dump segment once the current one exceeds this size.
+const SEGMENT_SIZE_LIMIT: usize = 1 << 20;
+
+// Top-level record tags
+const HPROF_UTF8: u8 = 0x01;
+const HPROF_LOAD_CLASS: u8 = 0x02;
+const HPROF_TRACE: u8 = 0x05;
+const HPROF_HEAP_DUMP_SEGMENT: u8 = 0x1C;
+const HPROF_HEAP_DUMP_END: u8 = 0x2C;
+
+// Sub-record tags in heap dump segments
+const HPROF_GC_ROOT_UNKNOWN: u8 = 0xFF;
+const HPROF_GC_ROOT_JNI_GLOBAL: u8 = 0x01;
+const HPROF_GC_ROOT_JAVA_FRAME: u8 = 0x03;
+const HPROF_GC_ROOT_STICKY_CLASS: u8 = 0x05;
+const HPROF_GC_ROOT_MONITOR_USED: u8 = 0x07;
+const HPROF_GC_CLASS_DUMP: u8 = 0x20;
+const HPROF_GC_INSTANCE_DUMP: u8 = 0x21;
+const HPROF_GC_OBJ_ARRAY_DUMP: u8 = 0x22;
+const HPROF_GC_PRIM_ARRAY_DUMP: u8 = 0x23;
+
+/// The HPROF type of references.  Primitive types use the same numbers as `BasicType`.
+const HPROF_NORMAL_OBJECT: u8 = 2;
+
+/// All objects refer to this (empty) stack trace.
+const STACK_TRACE_SERIAL: u32 = 1;
+
+/// The category of a GC root, which decides the sub-record we use for it.
+#[derive(Clone, Copy)]
+enum RootKind {
+    Unknown,
+    JniGlobal,
+    StickyClass,
+    MonitorUsed,
+    JavaFrame { thread_serial: u32 },
+}
+
+struct Field {
+    name_id: u64,
+    ty: u8,
+    offset: i32,
+}
+
+struct ClassInfo {
+    serial: u32,
+    mirror: Option<ObjectReference>,
+    super_klass: Option<Address>,
+    loader: Option<ObjectReference>,
+    name_id: u64,
+    instance_fields: Vec<Field>,
+    static_fields: Vec<Field>,
+}
+
+/// Convert a `BasicType` number to the HPROF type
+fn hprof_type(basic_type: i32) -> u8 {
+    if basic_type == BasicType::T_OBJECT as i32 || basic_type == BasicType::T_ARRAY as i32 {
+        HPROF_NORMAL_OBJECT
+    } else {
+        debug_assert!(
+            (BasicType::T_BOOLEAN as i32..=BasicType::T_LONG as i32).contains(&basic_type)
+        );
+        basic_type as u8
+    }
+}
+
+/// The name of `klass`, e.g. `java/lang/String`
+fn klass_name(klass: &Klass) -> String {
+    let mut buf = vec![0u8; 256];
+    loop {
+        let len = (upcalls().klass_name)(klass, buf.as_mut_ptr() as *mut libc::c_char, buf.len());
+        if len < buf.len() {
+            return String::from_utf8_lossy(&buf[..len]).into_owned();
+        }
+        // The name was truncated.
+        buf.resize(len + 1, 0);
+    }
+}
+
+/// The size of a value of the given HPROF type
+fn value_size(ty: u8) -> usize {
+    match ty {
+        HPROF_NORMAL_OBJECT => ID_SIZE as usize,
+        t if t == BasicType::T_BOOLEAN as u8 || t == BasicType::T_BYTE as u8 => 1,
+        t if t == BasicType::T_CHAR as u8 || t == BasicType::T_SHORT as u8 => 2,
+        t if t == BasicType::T_FLOAT as u8 || t == BasicType::T_INT as u8 => 4,
+        t if t == BasicType::T_DOUBLE as u8 || t == BasicType::T_LONG as u8 => 8,
+        _ => unreachable!("Unexpected HPROF type: {}", ty),
+    }
+}
+
+/// Big-endian serialization of HPROF values
+trait HprofBuffer {
+    fn put_u1(&mut self, v: u8);
+    fn put_u2(&mut self, v: u16);
+    fn put_u4(&mut self, v: u32);
+    fn put_u8(&mut self, v: u64);
+    fn put_id(&mut self, object: Option<ObjectReference>) {
+        self.put_u8(object.map_or(0, |o| o.to_raw_address().as_usize() as u64));
+    }
+    /// Copy a value of the given size from memory
+    fn put_raw(&mut self, addr: Address, size: usize) {
+        unsafe {
+            match size {
+                1 => self.put_u1(addr.load::<u8>()),
+                2 => self.put_u2(addr.load::<u16>()),
+                4 => self.put_u4(addr.load::<u32>()),
+                8 => self.put_u8(addr.load::<u64>()),
+                _ => unreachable!(),
+            }
+        }
+    }
+    /// Copy the value of a field from memory
+    fn put_value<const COMPRESSED: bool>(&mut self, addr: Address, ty: u8) {
+        if ty == HPROF_NORMAL_OBJECT {
+            self.put_id(OpenJDKSlot::<COMPRESSED>::from(addr).load());
+        } else {
+            self.put_raw(addr, value_size(ty));
+        }
+    }
+}
+
+impl HprofBuffer for Vec<u8> {
+    fn put_u1(&mut self, v: u8) {
+        self.push(v);
+    }
+    fn put_u2(&mut self, v: u16) {
+        self.extend_from_slice(&v.to_be_bytes());
+    }
+    fn put_u4(&mut self, v: u32) {
+        self.extend_from_slice(&v.to_be_bytes());
+    }
+    fn put_u8(&mut self, v: u64) {
+        self.extend_from_slice(&v.to_be_bytes());
+    }
+}
+
+fn for_each_object(mut f: impl FnMut(ObjectReference)) -> io::Result<()> {
+    if crate::api::mmtk_iterate_objects(HeapObjectClosure::from_rust_closure(&mut f)) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "heap dumps require the vo_bit feature",
+        ))
+    }
+}
+
+struct HeapDumpWriter<const COMPRESSED: bool> {
+    out: BufWriter<File>,
+    /// The heap dump segment being written
+    segment: Vec<u8>,
+    strings: HashMap<String, u64>,
+    /// Information of all dumped classes, indexed by the address of the `Klass`
+    classes: HashMap<Address, ClassInfo>,
+}
+
+impl<const COMPRESSED: bool> HeapDumpWriter<COMPRESSED> {
+    fn new(path: &str) -> io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+            segment: Vec::with_capacity(SEGMENT_SIZE_LIMIT),
+            strings: HashMap::new(),
+            classes: HashMap::new(),
+        })
+    }
+
+    fn write_record(&mut self, tag: u8, body: &[u8]) -> io::Result<()> {
+        self.out.write_all(&[tag])?;
+        // Time offset from the header timestamp
+        self.out.write_all(&0u32.to_be_bytes())?;
+        self.out.write_all(&(body.len() as u32).to_be_bytes())?;
+        self.out.write_all(body)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        self.out.write_all(HPROF_HEADER)?;
+        self.out.write_all(&ID_SIZE.to_be_bytes())?;
+        self.out.write_all(&millis.to_be_bytes())?;
+
+        let mut trace = vec![];
+        trace.put_u4(STACK_TRACE_SERIAL);
+        trace.put_u4(0); // thread serial
+        trace.put_u4(0); // number of frames
+        self.write_record(HPROF_TRACE, &trace)
+    }
+
+    /// Get the ID of a string, writing a UTF8 record for it if it is new.
+    fn intern(&mut self, s: &str) -> io::Result<u64> {
+        if let Some(id) = self.strings.get(s) {
+            return Ok(*id);
+        }
+        let id = self.strings.len() as u64 + 1;
+        let mut body = vec![];
+        body.put_u8(id);
+        body.extend_from_slice(s.as_bytes());
+        self.write_record(HPROF_UTF8, &body)?;
+        self.strings.insert(s.to_owned(), id);
+        Ok(id)
+    }
+
+    /// Find all classes with instances in the heap, their super classes, and the classes of all
+    /// mirrors in the heap.  Write a LOAD_CLASS record for each of them.
+    fn load_classes(&mut self) -> io::Result<()> {
+        let mut klasses: HashSet<Address> = HashSet::new();
+        for_each_object(|object| {
+            let oop = Oop::from(object);
+            let klass = oop.klass::<COMPRESSED>();
+            klasses.insert(Address::from_ref(klass));
+            if klass.id == KlassID::InstanceMirror {
+                if let Some(k) = InstanceMirrorKlass::klass_of_mirror(oop) {
+                    klasses.insert(Address::from_ref(k));
+                }
+            }
+        })?;
+
+        let mut worklist: Vec<Address> = klasses.into_iter().collect();
+        while let Some(klass_addr) = worklist.pop() {
+            if self.classes.contains_key(&klass_addr) {
+                continue;
+            }
+            let klass: &'static Klass = unsafe { &*klass_addr.to_ptr::<Klass>() };
+            let info = self.class_info(klass)?;
+            if let Some(super_klass) = info.super_klass {
+                worklist.push(super_klass);
+            }
+            let mut body = vec![];
+            body.put_u4(info.serial);
+            body.put_id(info.mirror);
+            body.put_u4(STACK_TRACE_SERIAL);
+            body.put_u8(info.name_id);
+            self.write_record(HPROF_LOAD_CLASS, &body)?;
+            self.classes.insert(klass_addr, info);
+        }
+        Ok(())
+    }
+
+    fn class_info(&mut self, klass: &'static Klass) -> io::Result<ClassInfo> {
+        let name_id = self.intern(&klass_name(klass))?;
+
+        let mut fields: Vec<(String, u8, i32, bool)> = vec![];
+        (upcalls().iterate_klass_fields)(
+            klass,
+            FieldClosure::from_rust_closure(&mut |name, basic_type, offset, is_static| {
+                fields.push((
+                    name.to_string_lossy().into_owned(),
+                    hprof_type(basic_type),
+                    offset,
+                    is_static,
+                ));
+            }),
+        );
+        let mut instance_fields = vec![];
+        let mut static_fields = vec![];
+        for (name, ty, offset, is_static) in fields {
+            let field = Field {
+                name_id: self.intern(&name)?,
+                ty,
+                offset,
+            };
+            if is_static {
+                static_fields.push(field);
+            } else {
+                instance_fields.push(field);
+            }
+        }
+
+        Ok(ClassInfo {
+            serial: self.classes.len() as u32 + 1,
+            mirror: klass.java_mirror(),
+            super_klass: klass.super_klass().map(Address::from_ref),
+            loader: (upcalls().klass_class_loader)(klass).into(),
+            name_id,
+            instance_fields,
+            static_fields,
+        })
+    }
+
+    fn mirror_of(&self, klass: Option<Address>) -> Option<ObjectReference> {
+        klass
+            .and_then(|k| self.classes.get(&k))
+            .and_then(|c| c.mirror)
+    }
+
+    /// The size of the field values in an instance dump of the class
+    fn instance_size(&self, klass: Address) -> usize {
+        let mut size = 0;
+        let mut cursor = Some(klass);
+        while let Some(info) = cursor.and_then(|k| self.classes.get(&k)) {
+            size += info
+                .instance_fields
+                .iter()
+                .map(|f| value_size(f.ty))
+                .sum::<usize>();
+            cursor = info.super_klass;
+        }
+        size
+    }
+
+    fn flush_segment_if_full(&mut self) -> io::Result<()> {
+        if self.segment.len() >= SEGMENT_SIZE_LIMIT {
+            self.flush_segment()?;
+        }
+        Ok(())
+    }
+
+    fn flush_segment(&mut self) -> io::Result<()> {
+        if !self.segment.is_empty() {
+            let segment = std::mem::take(&mut self.segment);
+            self.write_record(HPROF_HEAP_DUMP_SEGMENT, &segment)?;
+            self.segment = segment;
+            self.segment.clear();
+        }
+        Ok(())
+    }
+
+    fn dump_root_slots(
+        &mut self,
+        kind: RootKind,
+        slots: impl IntoIterator<Item = OpenJDKSlot<COMPRESSED>>,
+    ) -> io::Result<()> {
+        for slot in slots {
+            let Some(object) = slot.load() else {
+                continue;
+            };
+            match kind {
+                RootKind::Unknown => {
+                    self.segment.put_u1(HPROF_GC_ROOT_UNKNOWN);
+                    self.segment.put_id(Some(object));
+                }
+                RootKind::JniGlobal => {
+                    self.segment.put_u1(HPROF_GC_ROOT_JNI_GLOBAL);
+                    self.segment.put_id(Some(object));
+                    // We use the address of the handle as the JNI global reference ID.
+                    self.segment.put_u8(slot.addr.as_usize() as u64);
+                }
+                RootKind::StickyClass => {
+                    self.segment.put_u1(HPROF_GC_ROOT_STICKY_CLASS);
+                    self.segment.put_id(Some(object));
+                }
+                RootKind::MonitorUsed => {
+                    self.segment.put_u1(HPROF_GC_ROOT_MONITOR_USED);
+                    self.segment.put_id(Some(object));
+                }
+                RootKind::JavaFrame { thread_serial } => {
+                    self.segment.put_u1(HPROF_GC_ROOT_JAVA_FRAME);
+                    self.segment.put_id(Some(object));
+                    self.segment.put_u4(thread_serial);
+                    // We do not know the frame depth.
+                    self.segment.put_u4(u32::MAX);
+                }
+            }
+            self.flush_segment_if_full()?;
+        }
+        Ok(())
+    }
+
+    fn dump_roots(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for_each_root_category::<COMPRESSED>(|category, slots| {
+            if category.is_weak() {
+                return;
+            }
+            let kind = match category {
+                RootCategory::Universe
+                | RootCategory::SystemDictionary
+                | RootCategory::ClassLoaderDataGraph => RootKind::StickyClass,
+                RootCategory::JNIHandles => RootKind::JniGlobal,
+                RootCategory::ObjectSynchronizer => RootKind::MonitorUsed,
+                RootCategory::Mutator { index, .. } => RootKind::JavaFrame {
+                    thread_serial: index as u32 + 1,
+                },
+                _ => RootKind::Unknown,
+            };
+            if result.is_ok() {
+                result = self.dump_root_slots(kind, slots);
+            }
+        });
+        result
+    }
+
+    fn dump_classes(&mut self) -> io::Result<()> {
+        let mut klasses: Vec<Address> = self.classes.keys().copied().collect();
+        klasses.sort_by_key(|k| self.classes[k].serial);
+        for klass in klasses {
+            let info = &self.classes[&klass];
+            let mirror = info.mirror;
+            let mut body = vec![];
+            body.put_u1(HPROF_GC_CLASS_DUMP);
+            body.put_id(mirror);
+            body.put_u4(STACK_TRACE_SERIAL);
+            body.put_id(self.mirror_of(info.super_klass));
+            body.put_id(info.loader);
+            body.put_id(None); // signers
+            body.put_id(None); // protection domain
+            body.put_id(None); // reserved
+            body.put_id(None); // reserved
+            body.put_u4(self.instance_size(klass) as u32);
+            body.put_u2(0); // constant pool entries
+            body.put_u2(info.static_fields.len() as u16);
+            for field in info.static_fields.iter() {
+                body.put_u8(field.name_id);
+                body.put_u1(field.ty);
+                match mirror {
+                    Some(mirror) => {
+                        let addr = Oop::from(mirror).get_field_address(field.offset);
+                        body.put_value::<COMPRESSED>(addr, field.ty);
+                    }
+                    None => body.resize(body.len() + value_size(field.ty), 0),
+                }
+            }
+            body.put_u2(info.instance_fields.len() as u16);
+            for field in info.instance_fields.iter() {
+                body.put_u8(field.name_id);
+                body.put_u1(field.ty);
+            }
+            self.segment.extend_from_slice(&body);
+            self.flush_segment_if_full()?;
+        }
+        Ok(())
+    }
+
+    fn dump_instance(&mut self, oop: Oop, klass: &'static Klass) {
+        let klass_addr = Address::from_ref(klass);
+        let instance_size = self.instance_size(klass_addr);
+        let segment = &mut self.segment;
+        segment.put_u1(HPROF_GC_INSTANCE_DUMP);
+        segment.put_id(Some(ObjectReference::from(oop)));
+        segment.put_u4(STACK_TRACE_SERIAL);
+        segment.put_id(self.classes.get(&klass_addr).and_then(|c| c.mirror));
+        segment.put_u4(instance_size as u32);
+        let mut cursor = Some(klass_addr);
+        while let Some(info) = cursor.and_then(|k| self.classes.get(&k)) {
+            for field in info.instance_fields.iter() {
+                segment.put_value::<COMPRESSED>(oop.get_field_address(field.offset), field.ty);
+            }
+            cursor = info.super_klass;
+        }
+    }
+
+    fn dump_obj_array(&mut self, oop: Oop, klass: &'static Klass) {
+        let array = unsafe { oop.as_array_oop() };
+        let array_class = self.mirror_of(Some(Address::from_ref(klass)));
+        let segment = &mut self.segment;
+        segment.put_u1(HPROF_GC_OBJ_ARRAY_DUMP);
+        segment.put_id(Some(ObjectReference::from(oop)));
+        segment.put_u4(STACK_TRACE_SERIAL);
+        segment.put_u4(array.length::<COMPRESSED>() as u32);
+        segment.put_id(array_class);
+        for slot in unsafe { array.slice::<COMPRESSED>(BasicType::T_OBJECT) }.iter_slots() {
+            segment.put_id(slot.load());
+        }
+    }
+
+    fn dump_prim_array(&mut self, oop: Oop, klass: &'static Klass) {
+        let array = unsafe { oop.as_array_oop() };
+        let length = array.length::<COMPRESSED>() as usize;
+        let element_size = 1 << klass.array_log2_element_size();
+        let base = Address::from_ref(oop) + klass.array_header_in_bytes();
+        let segment = &mut self.segment;
+        segment.put_u1(HPROF_GC_PRIM_ARRAY_DUMP);
+        segment.put_id(Some(ObjectReference::from(oop)));
+        segment.put_u4(STACK_TRACE_SERIAL);
+        segment.put_u4(length as u32);
+        segment.put_u1(hprof_type(klass.array_element_type()));
+        if element_size == 1 {
+            let data = unsafe { std::slice::from_raw_parts(base.to_ptr::<u8>(), length) };
+            segment.extend_from_slice(data);
+        } else {
+            for i in 0..length {
+                segment.put_raw(base + i * element_size, element_size);
+            }
+        }
+    }
+
+    fn dump_object(&mut self, object: ObjectReference) -> io::Result<()> {
+        let oop = Oop::from(object);
+        let klass = oop.klass::<COMPRESSED>();
+        match klass.id {
+            // Mirrors of classes are written as class dumps.  Mirrors of primitive types are
+            // ordinary instances of `java.lang.Class`.
+            KlassID::InstanceMirror if InstanceMirrorKlass::klass_of_mirror(oop).is_some() => {}
+            KlassID::ObjArray => self.dump_obj_array(oop, klass),
+            KlassID::TypeArray => self.dump_prim_array(oop, klass),
+            _ => self.dump_instance(oop, klass),
+        }
+        self.flush_segment_if_full()
+    }
+
+    fn dump_objects(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for_each_object(|object| {
+            if result.is_ok() {
+                result = self.dump_object(object);
+            }
+        })?;
+        result
+    }
+
+    fn write(mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.load_classes()?;
+        self.dump_roots()?;
+        self.dump_classes()?;
+        self.dump_objects()?;
+        self.flush_segment()?;
+        self.write_record(HPROF_HEAP_DUMP_END, &[])?;
+        self.out.flush()
+    }
+}
+
+/// Write an HPROF heap dump of the whole heap to the file at `path`.
+pub fn dump_heap<const COMPRESSED: bool>(path: &str) -> io::Result<()> {
+    HeapDumpWriter::<COMPRESSED>::new(path)?.write()
+}
@@ -4,33 +4,50 @@ extern crate lazy_static;
 extern crate probe;
 
 use std::collections::HashMap;
-use std::ptr::null_mut;
+use std::ffi::CStr;
 use std::sync::Mutex;
 
-use libc::{c_char, c_void, uintptr_t};
-use mmtk::util::alloc::AllocationError;
+use abi::Klass;
+use libc::{c_char, uintptr_t};
+use mmtk::util::api_util::NullableObjectReference;
 use mmtk::util::constants::LOG_BYTES_IN_GBYTE;
+use mmtk::util::conversions;
 use mmtk::util::heap::vm_layout::VMLayout;
-use mmtk::util::{conversions, opaque_pointer::*};
 use mmtk::util::{Address, ObjectReference};
 use mmtk::vm::slot::Slot;
 use mmtk::vm::VMBinding;
 use mmtk::{MMTKBuilder, Mutator, MMTK};
 pub use slots::use_compressed_oops;
 use slots::{OpenJDKSlot, OpenJDKSlotRange};
+pub use upcalls::{upcalls, OpenJDK_Upcalls};
 
 mod abi;
 pub mod active_plan;
+mod alloc_sampling;
 pub mod api;
 mod build_info;
 pub mod collection;
+pub mod gc_listener;
+pub mod gc_stats;
+pub mod gc_trigger;
 mod gc_work;
+mod heap_dump;
 pub mod object_model;
 mod object_scanning;
+pub mod periodic_gc;
+mod pretenure;
 pub mod reference_glue;
+mod roots;
 pub mod scanning;
 mod slots;
+mod soft_references;
+#[cfg(test)]
+mod tests;
+pub mod tlab;
+mod upcalls;
+mod verify;
 pub(crate) mod vm_metadata;
+mod weak_roots;
 
 #[repr(C)]
 pub struct NewBuffer {
@@ -80,48 +97,149 @@ pub struct SlotsClosure {
     pub data: *const libc::c_void,
 }
 
+/// A closure for reporting heap objects.  The C++ code should pass `data` back as the last argument.
 #[repr(C)]
-pub struct OpenJDK_Upcalls {
-    pub stop_all_mutators: extern "C" fn(tls: VMWorkerThread, closure: MutatorClosure),
-    pub resume_mutators: extern "C" fn(tls: VMWorkerThread),
-    pub spawn_gc_thread: extern "C" fn(tls: VMThread, kind: libc::c_int, ctx: *mut libc::c_void),
-    pub block_for_gc: extern "C" fn(),
-    pub out_of_memory: extern "C" fn(tls: VMThread, err_kind: AllocationError),
-    pub get_mutators: extern "C" fn(closure: MutatorClosure),
-    pub scan_object: extern "C" fn(trace: *mut c_void, object: ObjectReference, tls: OpaquePointer),
-    pub dump_object: extern "C" fn(object: ObjectReference),
-    pub get_object_size: extern "C" fn(object: ObjectReference) -> usize,
-    pub get_mmtk_mutator: extern "C" fn(tls: VMMutatorThread) -> *mut libc::c_void,
-    pub is_mutator: extern "C" fn(tls: VMThread) -> bool,
-    pub harness_begin: extern "C" fn(),
-    pub harness_end: extern "C" fn(),
-    pub compute_klass_mem_layout_checksum: extern "C" fn() -> usize,
-    pub offset_of_static_fields: extern "C" fn() -> i32,
-    pub static_oop_field_count_offset: extern "C" fn() -> i32,
-    pub referent_offset: extern "C" fn() -> i32,
-    pub discovered_offset: extern "C" fn() -> i32,
-    pub dump_object_string: extern "C" fn(object: ObjectReference) -> *const c_char,
-    pub scan_roots_in_all_mutator_threads: extern "C" fn(closure: SlotsClosure),
-    pub scan_roots_in_mutator_thread: extern "C" fn(closure: SlotsClosure, tls: VMMutatorThread),
-    pub scan_universe_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_jni_handle_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_object_synchronizer_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_management_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_jvmti_export_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_aot_loader_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_system_dictionary_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_code_cache_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_string_table_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_class_loader_data_graph_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_weak_processor_roots: extern "C" fn(closure: SlotsClosure),
-    pub scan_vm_thread_roots: extern "C" fn(closure: SlotsClosure),
-    pub number_of_mutators: extern "C" fn() -> usize,
-    pub schedule_finalizer: extern "C" fn(),
-    pub prepare_for_roots_re_scanning: extern "C" fn(),
-    pub enqueue_references: extern "C" fn(objects: *const ObjectReference, len: usize),
-}
-
-pub static mut UPCALLS: *const OpenJDK_Upcalls = null_mut();
+pub struct HeapObjectClosure {
+    pub func: extern "C" fn(object: ObjectReference, data: *mut libc::c_void),
+    pub data: *mut libc::c_void,
+}
+
+impl HeapObjectClosure {
+    fn from_rust_closure<F>(callback: &mut F) -> Self
+    where
+        F: FnMut(ObjectReference),
+    {
+        Self {
+            func: Self::call_rust_closure::<F>,
+            data: callback as *mut F as *mut libc::c_void,
+        }
+    }
+
+    extern "C" fn call_rust_closure<F>(object: ObjectReference, callback_ptr: *mut libc::c_void)
+    where
+        F: FnMut(ObjectReference),
+    {
+        let callback: &mut F = unsafe { &mut *(callback_ptr as *mut F) };
+        callback(object);
+    }
+}
+
+/// A closure for processing weak roots.  `func` returns the (possibly moved) object if `object` is
+/// live, or null if it is dead.  The C++ code should pass `data` back as the last argument.
+#[repr(C)]
+pub struct WeakRootsClosure {
+    pub func:
+        extern "C" fn(object: ObjectReference, data: *mut libc::c_void) -> NullableObjectReference,
+    pub data: *mut libc::c_void,
+}
+
+impl WeakRootsClosure {
+    fn from_rust_closure<F>(callback: &mut F) -> Self
+    where
+        F: FnMut(ObjectReference) -> Option<ObjectReference>,
+    {
+        Self {
+            func: Self::call_rust_closure::<F>,
+            data: callback as *mut F as *mut libc::c_void,
+        }
+    }
+
+    extern "C" fn call_rust_closure<F>(
+        object: ObjectReference,
+        callback_ptr: *mut libc::c_void,
+    ) -> NullableObjectReference
+    where
+        F: FnMut(ObjectReference) -> Option<ObjectReference>,
+    {
+        let callback: &mut F = unsafe { &mut *(callback_ptr as *mut F) };
+        callback(object).into()
+    }
+}
+
+/// A closure for reporting the fields declared in a class.  The C++ code should pass `data` back
+/// as the last argument.  `basic_type` uses the numbering of `BasicType`, and `offset` is relative
+/// to the start of the object, or to the start of the mirror for static fields.
+#[repr(C)]
+pub struct FieldClosure {
+    pub func: extern "C" fn(
+        name: *const c_char,
+        basic_type: i32,
+        offset: i32,
+        is_static: bool,
+        data: *mut libc::c_void,
+    ),
+    pub data: *mut libc::c_void,
+}
+
+impl FieldClosure {
+    fn from_rust_closure<F>(callback: &mut F) -> Self
+    where
+        F: FnMut(&CStr, i32, i32, bool),
+    {
+        Self {
+            func: Self::call_rust_closure::<F>,
+            data: callback as *mut F as *mut libc::c_void,
+        }
+    }
+
+    extern "C" fn call_rust_closure<F>(
+        name: *const c_char,
+        basic_type: i32,
+        offset: i32,
+        is_static: bool,
+        callback_ptr: *mut libc::c_void,
+    ) where
+        F: FnMut(&CStr, i32, i32, bool),
+    {
+        let callback: &mut F = unsafe { &mut *(callback_ptr as *mut F) };
+        callback(
+            unsafe { CStr::from_ptr(name) },
+            basic_type,
+            offset,
+            is_static,
+        );
+    }
+}
+
+/// A closure for reporting the memory layout of klasses, for `abi::validate_memory_layouts`.
+/// `value` is a struct size or a field offset if `klass` is null, or a value that the VM read from
+/// `klass` otherwise.  The C++ code should pass `data` back as the last argument.
+#[repr(C)]
+pub struct KlassLayoutClosure {
+    pub func: extern "C" fn(
+        name: *const c_char,
+        value: isize,
+        klass: *const Klass,
+        data: *mut libc::c_void,
+    ),
+    pub data: *mut libc::c_void,
+}
+
+impl KlassLayoutClosure {
+    fn from_rust_closure<F>(callback: &mut F) -> Self
+    where
+        F: FnMut(&CStr, isize, Option<&'static Klass>),
+    {
+        Self {
+            func: Self::call_rust_closure::<F>,
+            data: callback as *mut F as *mut libc::c_void,
+        }
+    }
+
+    extern "C" fn call_rust_closure<F>(
+        name: *const c_char,
+        value: isize,
+        klass: *const Klass,
+        callback_ptr: *mut libc::c_void,
+    ) where
+        F: FnMut(&CStr, isize, Option<&'static Klass>),
+    {
+        let callback: &mut F = unsafe { &mut *(callback_ptr as *mut F) };
+        callback(unsafe { CStr::from_ptr(name) }, value, unsafe {
+            klass.as_ref()
+        });
+    }
+}
 
 #[no_mangle]
 pub static GLOBAL_SIDE_METADATA_BASE_ADDRESS: uintptr_t =
@@ -139,6 +257,10 @@ pub static VO_BIT_ADDRESS: uintptr_t =
 pub static FREE_LIST_ALLOCATOR_SIZE: uintptr_t =
     std::mem::size_of::<mmtk::util::alloc::FreeListAllocator<OpenJDK<false>>>();
 
+/// The VM keeps the start of the TLAB of each mutator after the mutator.  See `tlab.rs`.
+#[no_mangle]
+pub static MUTATOR_SIZE: uintptr_t = std::mem::size_of::<mmtk::Mutator<OpenJDK<false>>>();
+
 #[no_mangle]
 pub static mut CONCURRENT_MARKING_ACTIVE: u8 = 0;
 
@@ -156,7 +278,10 @@ impl<const COMPRESSED: bool> VMBinding for OpenJDK<COMPRESSED> {
     type VMMemorySlice = OpenJDKSlotRange<COMPRESSED>;
 
     const MIN_ALIGNMENT: usize = 8;
-    const MAX_ALIGNMENT: usize = 8;
+    // `MAX_ALIGNMENT` is a constant, but `ObjectAlignmentInBytes` is only known when the VM starts.
+    // So it is the largest object alignment HotSpot accepts rather than the one in use.  Objects
+    // are still allocated and copied with the alignment in use (see `object_model.rs`).
+    const MAX_ALIGNMENT: usize = object_model::MAX_OBJECT_ALIGNMENT;
     const USE_ALLOCATION_OFFSET: bool = false;
 }
 
@@ -212,24 +337,44 @@ lazy_static! {
     static ref MATURE_CODE_CACHE_ROOTS: Mutex<HashMap<Address, Vec<Address>>> = Mutex::new(HashMap::new());
 }
 
+/// The start of the heap with compressed oops
+const COMPRESSED_HEAP_START: usize = 0x4000_0000;
+
+/// Choose the heap range so that compressed oops can address it.
 fn set_compressed_pointer_vm_layout(builder: &mut MMTKBuilder) {
-    let max_heap_size = builder.options.gc_trigger.max_heap_size();
+    let max_heap_size = gc_trigger::max_heap_size(&builder.options.gc_trigger);
+    let constants =
+        compressed_pointer_vm_layout(max_heap_size, object_model::log_object_alignment());
+    builder.set_vm_layout(constants);
+}
+
+/// The VM layout for a heap of at most `max_heap_size` bytes with compressed oops.  Narrow oops
+/// are 32-bit offsets in units of the object alignment, so they can address
+/// `4 GB << log_alignment`, i.e. 32 GB with the default 8-byte alignment, and 64 GB or 128 GB with
+/// 16-byte or 32-byte alignment.  `slots::compressed_oops_base_and_shift` chooses the encoding
+/// from the resulting heap range.
+pub(crate) fn compressed_pointer_vm_layout(max_heap_size: usize, log_alignment: usize) -> VMLayout {
+    let max_encoded_size = (4usize << LOG_BYTES_IN_GBYTE) << log_alignment;
     assert!(
-        max_heap_size <= (32usize << LOG_BYTES_IN_GBYTE),
-        "Heap size is larger than 32 GB"
+        max_heap_size <= max_encoded_size,
+        "Heap size is larger than {} GB.  Use a larger ObjectAlignmentInBytes.",
+        max_encoded_size >> LOG_BYTES_IN_GBYTE
     );
-    let start = 0x4000_0000;
+    let start = COMPRESSED_HEAP_START;
     let end = match start + max_heap_size {
-        end if end <= (4usize << 30) => 4usize << 30,
-        end if end <= (32usize << 30) => 32usize << 30,
-        _ => 0x4000_0000 + (32usize << 30),
+        // Zero-based, unshifted
+        end if end <= (4usize << LOG_BYTES_IN_GBYTE) => 4usize << LOG_BYTES_IN_GBYTE,
+        // Zero-based, shifted
+        end if end <= max_encoded_size => max_encoded_size,
+        // The base is below the start of the heap, and the heap must end before the largest
+        // narrow oop.
+        _ => start - slots::HEAP_BASE_GAP + max_encoded_size,
     };
-    let constants = VMLayout {
-        log_address_space: 35,
+    VMLayout {
+        log_address_space: 32 + log_alignment,
         heap_start: conversions::chunk_align_down(unsafe { Address::from_usize(start) }),
-        heap_end: conversions::chunk_align_up(unsafe { Address::from_usize(end) }),
+        heap_end: conversions::chunk_align_down(unsafe { Address::from_usize(end) }),
         log_space_extent: 31,
         force_use_contiguous_spaces: false,
-    };
-    builder.set_vm_layout(constants);
+    }
 }
@@ -1,10 +1,42 @@
 use crate::abi::Oop;
-use crate::UPCALLS;
+use crate::upcalls;
 use crate::{vm_metadata, OpenJDK};
 use mmtk::util::alloc::fill_alignment_gap;
+use mmtk::util::constants::{BYTES_IN_WORD, LOG_BYTES_IN_WORD};
 use mmtk::util::copy::*;
 use mmtk::util::{Address, ObjectReference};
 use mmtk::vm::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The largest `ObjectAlignmentInBytes` the binding supports, which is also the largest value
+/// HotSpot accepts.  It is `MAX_ALIGNMENT` of the binding.
+pub const MAX_OBJECT_ALIGNMENT: usize = 256;
+
+/// log2 of HotSpot's `ObjectAlignmentInBytes`
+static LOG_OBJECT_ALIGNMENT: AtomicUsize = AtomicUsize::new(LOG_BYTES_IN_WORD as usize);
+
+/// Set the object alignment to HotSpot's `ObjectAlignmentInBytes`.  Must be called before
+/// `openjdk_gc_init`.
+pub fn set_object_alignment(bytes: usize) {
+    assert!(
+        bytes.is_power_of_two() && (BYTES_IN_WORD..=MAX_OBJECT_ALIGNMENT).contains(&bytes),
+        "Unsupported ObjectAlignmentInBytes: {}",
+        bytes
+    );
+    LOG_OBJECT_ALIGNMENT.store(bytes.trailing_zeros() as usize, Ordering::Relaxed);
+}
+
+/// HotSpot's `ObjectAlignmentInBytes`.  All objects start at multiples of it, and their sizes are
+/// multiples of it.  The allocation fast paths compiled by HotSpot rely on the latter to keep the
+/// bump pointer aligned.
+pub fn object_alignment() -> usize {
+    1 << log_object_alignment()
+}
+
+/// log2 of `object_alignment()`.  It is the shift of compressed oops beyond 4 GB.
+pub fn log_object_alignment() -> usize {
+    LOG_OBJECT_ALIGNMENT.load(Ordering::Relaxed)
+}
 
 pub struct VMObjectModel<const COMPRESSED: bool> {}
 
@@ -27,7 +59,7 @@ impl<const COMPRESSED: bool> ObjectModel<OpenJDK<COMPRESSED>> for VMObjectModel<
         copy_context: &mut GCWorkerCopyContext<OpenJDK<COMPRESSED>>,
     ) -> ObjectReference {
         let bytes = unsafe { Oop::from(from).size::<COMPRESSED>() };
-        let dst = copy_context.alloc_copy(from, bytes, ::std::mem::size_of::<usize>(), 0, copy);
+        let dst = copy_context.alloc_copy(from, bytes, object_alignment(), 0, copy);
         debug_assert!(!dst.is_zero());
         // Copy
         let src = from.to_raw_address();
@@ -71,8 +103,7 @@ impl<const COMPRESSED: bool> ObjectModel<OpenJDK<COMPRESSED>> for VMObjectModel<
     }
 
     fn get_align_when_copied(_object: ObjectReference) -> usize {
-        // FIXME figure out the proper alignment
-        ::std::mem::size_of::<usize>()
+        object_alignment()
     }
 
     fn get_align_offset_when_copied(_object: ObjectReference) -> usize {
@@ -92,9 +123,7 @@ impl<const COMPRESSED: bool> ObjectModel<OpenJDK<COMPRESSED>> for VMObjectModel<
     }
 
     fn dump_object(object: ObjectReference) {
-        unsafe {
-            ((*UPCALLS).dump_object)(object);
-        }
+        (upcalls().dump_object)(object);
     }
 
     fn is_object_sane(object: ObjectReference) -> bool {
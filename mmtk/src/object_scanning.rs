@@ -1,62 +1,159 @@
 use crate::OpenJDKSlot;
 
 use super::abi::*;
-use super::UPCALLS;
+use crate::upcalls;
+use mmtk::util::constants::LOG_BYTES_IN_WORD;
 use mmtk::util::opaque_pointer::*;
 use mmtk::util::{Address, ObjectReference};
 use mmtk::vm::SlotVisitor;
-use std::cell::UnsafeCell;
+use std::cell::{RefCell, UnsafeCell};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{mem, slice};
 
 type S<const COMPRESSED: bool> = OpenJDKSlot<COMPRESSED>;
 
-trait OopIterate: Sized {
-    fn oop_iterate<const COMPRESSED: bool>(
-        &self,
-        oop: Oop,
-        closure: &mut impl SlotVisitor<OpenJDKSlot<COMPRESSED>>,
-    );
+/// How to scan the objects of a klass.  Deriving it from a klass reads the klass ID, and the
+/// vtable and itable lengths to find the oop maps, so we cache it per klass.  See `scan_plan`.
+#[derive(Clone, Copy)]
+enum ScanPlan {
+    /// Instances, including class loaders
+    Instance {
+        oop_maps: &'static [OopMapBlock],
+    },
+    /// `java.lang.Class` instances, which also hold the static fields of the class they represent.
+    /// The static fields start at `static_fields_offset`, and their number is stored in the mirror
+    /// at `static_oop_field_count_offset`.
+    Mirror {
+        oop_maps: &'static [OopMapBlock],
+        static_fields_offset: usize,
+        static_oop_field_count_offset: i32,
+    },
+    /// `java.lang.ref.Reference` instances
+    Reference {
+        oop_maps: &'static [OopMapBlock],
+        reference_type: ReferenceType,
+    },
+    ObjArray,
+    /// Primitive arrays, which contain no reference fields
+    TypeArray,
 }
 
-impl OopIterate for OopMapBlock {
-    fn oop_iterate<const COMPRESSED: bool>(
-        &self,
-        oop: Oop,
-        closure: &mut impl SlotVisitor<S<COMPRESSED>>,
-    ) {
-        let log_bytes_in_oop = if COMPRESSED { 2 } else { 3 };
-        let start = oop.get_field_address(self.offset);
-        for i in 0..self.count as usize {
-            let slot = (start + (i << log_bytes_in_oop)).into();
-            closure.visit_slot(slot);
+impl ScanPlan {
+    fn new<const COMPRESSED: bool>(klass: &'static Klass, oop: Oop) -> Self {
+        let klass_id = klass.id;
+        assert!(
+            klass_id as i32 >= 0 && (klass_id as i32) < 6,
+            "Invalid klass-id: {:x} for oop: {:x}",
+            klass_id as i32,
+            unsafe { mem::transmute::<Oop, ObjectReference>(oop) }
+        );
+        match klass_id {
+            KlassID::Instance | KlassID::InstanceClassLoader => {
+                let instance_klass = unsafe { klass.cast::<InstanceKlass>() };
+                ScanPlan::Instance {
+                    oop_maps: instance_klass.nonstatic_oop_maps(),
+                }
+            }
+            KlassID::InstanceMirror => {
+                let instance_klass = unsafe { klass.cast::<InstanceMirrorKlass>() };
+                ScanPlan::Mirror {
+                    oop_maps: instance_klass.instance_klass.nonstatic_oop_maps(),
+                    static_fields_offset: InstanceMirrorKlass::offset_of_static_fields(),
+                    static_oop_field_count_offset:
+                        InstanceMirrorKlass::static_oop_field_count_offset(),
+                }
+            }
+            KlassID::InstanceRef => {
+                let instance_klass = unsafe { klass.cast::<InstanceRefKlass>() };
+                ScanPlan::Reference {
+                    oop_maps: instance_klass.instance_klass.nonstatic_oop_maps(),
+                    reference_type: instance_klass.instance_klass.reference_type,
+                }
+            }
+            KlassID::ObjArray => ScanPlan::ObjArray,
+            KlassID::TypeArray => ScanPlan::TypeArray,
         }
     }
 }
 
-impl OopIterate for InstanceKlass {
-    fn oop_iterate<const COMPRESSED: bool>(
-        &self,
-        oop: Oop,
-        closure: &mut impl SlotVisitor<S<COMPRESSED>>,
-    ) {
-        let oop_maps = self.nonstatic_oop_maps();
-        for map in oop_maps {
-            map.oop_iterate::<COMPRESSED>(oop, closure)
+/// The number of entries of the scan plan cache of each thread.  It is direct-mapped, indexed by
+/// the klass address.
+const SCAN_PLAN_CACHE_SIZE: usize = 1024;
+
+/// Incremented to invalidate the scan plan caches of all threads
+static SCAN_PLAN_EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+struct ScanPlanCache {
+    epoch: usize,
+    entries: Vec<Option<(Address, ScanPlan)>>,
+}
+
+thread_local! {
+    static SCAN_PLAN_CACHE: RefCell<ScanPlanCache> = RefCell::new(ScanPlanCache {
+        epoch: 0,
+        entries: vec![None; SCAN_PLAN_CACHE_SIZE],
+    });
+}
+
+/// Invalidate the cached scan plans of all threads.  A klass may be freed when its class is
+/// unloaded, and its address reused for another klass, so the VM must call this (through
+/// `mmtk_invalidate_scan_plans`) whenever it unloads classes.  Redefining a class does not
+/// invalidate its plan: HotSpot keeps the `InstanceKlass`, and redefinition may not change the
+/// fields or the virtual and interface methods, so the vtable and itable lengths and the oop maps
+/// stay the same.
+pub(crate) fn invalidate_scan_plans() {
+    SCAN_PLAN_EPOCH.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Get the scan plan of the klass of `oop` from the cache of the current thread, or create it.
+fn scan_plan<const COMPRESSED: bool>(oop: Oop) -> ScanPlan {
+    let klass = oop.klass::<COMPRESSED>();
+    let klass_addr = Address::from_ref(klass);
+    SCAN_PLAN_CACHE.with_borrow_mut(|cache| {
+        let epoch = SCAN_PLAN_EPOCH.load(Ordering::Relaxed);
+        if cache.epoch != epoch {
+            cache.entries.fill(None);
+            cache.epoch = epoch;
+        }
+        let index = (klass_addr.as_usize() >> LOG_BYTES_IN_WORD) & (SCAN_PLAN_CACHE_SIZE - 1);
+        match cache.entries[index] {
+            Some((cached_klass, plan)) if cached_klass == klass_addr => plan,
+            _ => {
+                let plan = ScanPlan::new::<COMPRESSED>(klass, oop);
+                cache.entries[index] = Some((klass_addr, plan));
+                plan
+            }
+        }
+    })
+}
+
+fn iterate_oop_maps<const COMPRESSED: bool>(
+    oop_maps: &[OopMapBlock],
+    oop: Oop,
+    closure: &mut impl SlotVisitor<S<COMPRESSED>>,
+) {
+    let log_bytes_in_oop = if COMPRESSED { 2 } else { 3 };
+    for map in oop_maps {
+        let start = oop.get_field_address(map.offset);
+        for i in 0..map.count as usize {
+            let slot = (start + (i << log_bytes_in_oop)).into();
+            closure.visit_slot(slot);
         }
     }
 }
 
-impl OopIterate for InstanceMirrorKlass {
-    fn oop_iterate<const COMPRESSED: bool>(
-        &self,
+impl InstanceMirrorKlass {
+    fn iterate_static_fields<const COMPRESSED: bool>(
         oop: Oop,
+        static_fields_offset: usize,
+        static_oop_field_count_offset: i32,
         closure: &mut impl SlotVisitor<S<COMPRESSED>>,
     ) {
-        self.instance_klass.oop_iterate::<COMPRESSED>(oop, closure);
-
-        // static fields
-        let start = Self::start_of_static_fields(oop);
-        let len = Self::static_oop_field_count(oop);
+        let start = Address::from_ref(oop) + static_fields_offset;
+        let len = unsafe {
+            oop.get_field_address(static_oop_field_count_offset)
+                .load::<i32>()
+        };
         if COMPRESSED {
             let start: *const NarrowOop = start.to_ptr::<NarrowOop>();
             let slice = unsafe { slice::from_raw_parts(start, len as _) };
@@ -73,19 +170,8 @@ impl OopIterate for InstanceMirrorKlass {
     }
 }
 
-impl OopIterate for InstanceClassLoaderKlass {
-    fn oop_iterate<const COMPRESSED: bool>(
-        &self,
-        oop: Oop,
-        closure: &mut impl SlotVisitor<S<COMPRESSED>>,
-    ) {
-        self.instance_klass.oop_iterate::<COMPRESSED>(oop, closure);
-    }
-}
-
-impl OopIterate for ObjArrayKlass {
-    fn oop_iterate<const COMPRESSED: bool>(
-        &self,
+impl ObjArrayKlass {
+    fn iterate_elements<const COMPRESSED: bool>(
         oop: Oop,
         closure: &mut impl SlotVisitor<S<COMPRESSED>>,
     ) {
@@ -102,61 +188,63 @@ impl OopIterate for ObjArrayKlass {
     }
 }
 
-impl OopIterate for TypeArrayKlass {
-    fn oop_iterate<const COMPRESSED: bool>(
-        &self,
-        _oop: Oop,
-        _closure: &mut impl SlotVisitor<S<COMPRESSED>>,
-    ) {
-        // Performance tweak: We skip processing the klass pointer since all
-        // TypeArrayKlasses are guaranteed processed via the null class loader.
-    }
-}
-
-impl OopIterate for InstanceRefKlass {
-    fn oop_iterate<const COMPRESSED: bool>(
-        &self,
+impl InstanceRefKlass {
+    fn iterate_reference_fields<const COMPRESSED: bool>(
+        reference_type: ReferenceType,
         oop: Oop,
         closure: &mut impl SlotVisitor<S<COMPRESSED>>,
     ) {
-        use crate::abi::*;
         use crate::api::{add_phantom_candidate, add_soft_candidate, add_weak_candidate};
-        self.instance_klass.oop_iterate::<COMPRESSED>(oop, closure);
 
         // Unlike OpenJDK's built-in collectors, we do not use the `discovered` field for
         // recording discovered references (we use `add_{soft,weak,phantom}_candidate`).
         // The `discovered` field links all `Reference` instances that are either in the
         // global "reference pending list" or given to the `ReferenceHandler` thread.
+        // (A registered `FinalReference` points it to itself.  See `discover_final_reference`.)
         // We treat it as a strong field.
         let discovered_addr: OpenJDKSlot<COMPRESSED> = Self::discovered_address::<COMPRESSED>(oop);
         closure.visit_slot(discovered_addr);
 
         if Self::should_scan_weak_refs::<COMPRESSED>() {
             let reference = ObjectReference::from(oop);
-            match self.instance_klass.reference_type {
+            match reference_type {
                 ReferenceType::None => {
                     panic!("oop_iterate on InstanceRefKlass with reference_type as None")
                 }
-                ReferenceType::Weak => add_weak_candidate(reference),
-                ReferenceType::Soft => add_soft_candidate(reference),
-                ReferenceType::Phantom => add_phantom_candidate(reference),
-                // Process these two types normally (as if they are strong refs)
-                // We will handle final reference later
-                ReferenceType::Final | ReferenceType::Other => {
-                    Self::process_ref_as_strong(oop, closure)
+                ReferenceType::Weak => {
+                    crate::gc_stats::count_discovered_reference();
+                    add_weak_candidate(reference)
+                }
+                ReferenceType::Soft => {
+                    crate::gc_stats::count_discovered_reference();
+                    if crate::soft_references::should_clear(oop) {
+                        add_weak_candidate(reference)
+                    } else {
+                        add_soft_candidate(reference)
+                    }
+                }
+                ReferenceType::Phantom => {
+                    crate::gc_stats::count_discovered_reference();
+                    add_phantom_candidate(reference)
+                }
+                ReferenceType::Final => {
+                    if !crate::reference_glue::discover_final_reference::<COMPRESSED>(oop) {
+                        Self::process_ref_as_strong(oop, closure)
+                    }
                 }
+                // Process this type normally (as if it is a strong ref)
+                ReferenceType::Other => Self::process_ref_as_strong(oop, closure),
             }
         } else {
             Self::process_ref_as_strong(oop, closure);
         }
     }
-}
-
-impl InstanceRefKlass {
     fn should_scan_weak_refs<const COMPRESSED: bool>() -> bool {
-        !*crate::singleton::<COMPRESSED>()
-            .get_options()
-            .no_reference_types
+        // The heap verifier treats references as strong, and must not discover them.
+        !crate::verify::is_verifying()
+            && !*crate::singleton::<COMPRESSED>()
+                .get_options()
+                .no_reference_types
     }
     fn process_ref_as_strong<const COMPRESSED: bool>(
         oop: Oop,
@@ -175,7 +263,7 @@ fn oop_iterate_slow<const COMPRESSED: bool, V: SlotVisitor<S<COMPRESSED>>>(
 ) {
     unsafe {
         CLOSURE.with(|x| *x.get() = closure as *mut V as *mut u8);
-        ((*UPCALLS).scan_object)(
+        (upcalls().scan_object)(
             mem::transmute::<*const unsafe extern "C" fn(Address), *mut libc::c_void>(
                 scan_object_fn::<COMPRESSED, V> as *const unsafe extern "C" fn(slot: Address),
             ),
@@ -186,38 +274,32 @@ fn oop_iterate_slow<const COMPRESSED: bool, V: SlotVisitor<S<COMPRESSED>>>(
 }
 
 fn oop_iterate<const COMPRESSED: bool>(oop: Oop, closure: &mut impl SlotVisitor<S<COMPRESSED>>) {
-    let klass = oop.klass::<COMPRESSED>();
-    let klass_id = klass.id;
-    assert!(
-        klass_id as i32 >= 0 && (klass_id as i32) < 6,
-        "Invalid klass-id: {:x} for oop: {:x}",
-        klass_id as i32,
-        unsafe { mem::transmute::<Oop, ObjectReference>(oop) }
-    );
-    match klass_id {
-        KlassID::Instance => {
-            let instance_klass = unsafe { klass.cast::<InstanceKlass>() };
-            instance_klass.oop_iterate::<COMPRESSED>(oop, closure);
+    match scan_plan::<COMPRESSED>(oop) {
+        ScanPlan::Instance { oop_maps } => iterate_oop_maps::<COMPRESSED>(oop_maps, oop, closure),
+        ScanPlan::Mirror {
+            oop_maps,
+            static_fields_offset,
+            static_oop_field_count_offset,
+        } => {
+            iterate_oop_maps::<COMPRESSED>(oop_maps, oop, closure);
+            InstanceMirrorKlass::iterate_static_fields::<COMPRESSED>(
+                oop,
+                static_fields_offset,
+                static_oop_field_count_offset,
+                closure,
+            );
         }
-        KlassID::InstanceClassLoader => {
-            let instance_klass = unsafe { klass.cast::<InstanceClassLoaderKlass>() };
-            instance_klass.oop_iterate::<COMPRESSED>(oop, closure);
+        ScanPlan::Reference {
+            oop_maps,
+            reference_type,
+        } => {
+            iterate_oop_maps::<COMPRESSED>(oop_maps, oop, closure);
+            InstanceRefKlass::iterate_reference_fields::<COMPRESSED>(reference_type, oop, closure);
         }
-        KlassID::InstanceMirror => {
-            let instance_klass = unsafe { klass.cast::<InstanceMirrorKlass>() };
-            instance_klass.oop_iterate::<COMPRESSED>(oop, closure);
-        }
-        KlassID::ObjArray => {
-            let array_klass = unsafe { klass.cast::<ObjArrayKlass>() };
-            array_klass.oop_iterate::<COMPRESSED>(oop, closure);
-        }
-        KlassID::TypeArray => {
+        ScanPlan::ObjArray => ObjArrayKlass::iterate_elements::<COMPRESSED>(oop, closure),
+        ScanPlan::TypeArray => {
             // Skip scanning primitive arrays as they contain no reference fields.
         }
-        KlassID::InstanceRef => {
-            let instance_klass = unsafe { klass.cast::<InstanceRefKlass>() };
-            instance_klass.oop_iterate::<COMPRESSED>(oop, closure);
-        }
     }
 }
 
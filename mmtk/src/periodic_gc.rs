@@ -0,0 +1,215 @@
+//! Periodic GC.  If no GC has happened for a configurable interval, the binding requests a GC so
+//! that the garbage of an idle VM is collected, and MMTk frees the blocks and chunks that became
+//! empty.  Freed pages are returned to the page resources of the spaces, which keep them mapped.
+//! So at the end of a periodic GC, while the mutators are still stopped, we also tell the OS that
+//! it can discard the pages of the chunks that no longer hold any object (`madvise(MADV_DONTNEED)`).
+//! The pages stay mapped, and are zero when the spaces use them again.  The decrease in the
+//! resident set size is reported as `GCStats::released_bytes`.
+//!
+//! Finding the free chunks relies on the valid-object (VO) bits.  Without the `vo_bit` feature,
+//! periodic GCs do not release any memory.
+//!
+//! The VM runs a thread that calls `mmtk_periodic_gc_poll` in a loop.  The call performs a GC if
+//! one is due, and returns how long the thread should wait before polling again.
+
+use crate::gc_stats::{self, nanos_now, GCCause};
+use mmtk::memory_manager;
+use mmtk::util::opaque_pointer::VMMutatorThread;
+#[cfg(feature = "vo_bit")]
+use mmtk::util::Address;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+const NANOS_PER_MILLI: u64 = 1_000_000;
+
+/// The idle interval in milliseconds, or 0 if periodic GC is disabled
+static PERIODIC_GC_INTERVAL: AtomicU64 = AtomicU64::new(0);
+/// When periodic GC was enabled.  We do not count the time before that as idle.
+static ENABLED_SINCE: AtomicU64 = AtomicU64::new(0);
+/// Set when we request a periodic GC, and cleared when the GC starts.
+static PERIODIC_GC_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// Set when a periodic GC starts, and cleared when it releases the free chunks.
+static IN_PERIODIC_GC: AtomicBool = AtomicBool::new(false);
+
+/// Set the idle interval in milliseconds.  0 disables periodic GC.
+pub fn set_periodic_gc_interval(interval_ms: u64) {
+    ENABLED_SINCE.store(nanos_now(), Ordering::Relaxed);
+    PERIODIC_GC_INTERVAL.store(interval_ms, Ordering::Relaxed);
+}
+
+pub fn periodic_gc_interval() -> u64 {
+    PERIODIC_GC_INTERVAL.load(Ordering::Relaxed)
+}
+
+/// Called when a GC starts.  Returns true if the GC is the periodic GC we requested.
+pub(crate) fn take_periodic_gc_request() -> bool {
+    let requested = PERIODIC_GC_REQUESTED.swap(false, Ordering::Relaxed);
+    IN_PERIODIC_GC.store(requested, Ordering::Relaxed);
+    requested
+}
+
+/// Called before we request the VM to resume all mutators.  At the end of a periodic GC, release
+/// the free chunks of the spaces to the OS.
+pub(crate) fn on_resume_mutators<const COMPRESSED: bool>() {
+    if IN_PERIODIC_GC.swap(false, Ordering::Relaxed) {
+        let released = release_free_chunks::<COMPRESSED>();
+        gc_stats::set_released_bytes(released);
+    }
+}
+
+/// Tell the OS to discard the pages of all chunks in MMTk spaces that hold no object.  Must be
+/// called while the mutators are stopped.  Returns the decrease in the resident set size.
+#[cfg(feature = "vo_bit")]
+fn release_free_chunks<const COMPRESSED: bool>() -> usize {
+    use crate::object_model::VMObjectModel;
+    use mmtk::util::conversions;
+    use mmtk::util::heap::vm_layout::{vm_layout, BYTES_IN_CHUNK};
+    use mmtk::util::ObjectReference;
+    use mmtk::vm::ObjectModel;
+
+    let mmtk = crate::singleton::<COMPRESSED>();
+    // The chunks that hold a part of an object, sorted
+    let mut used_chunks: Vec<Address> = vec![];
+    memory_manager::enumerate_objects(mmtk, |object| {
+        let start = VMObjectModel::<COMPRESSED>::ref_to_object_start(object);
+        let end = start + VMObjectModel::<COMPRESSED>::get_current_size(object);
+        let mut chunk = conversions::chunk_align_down(start);
+        while chunk < end {
+            // Objects are enumerated in address order within a chunk, so this removes most
+            // duplicates.
+            if used_chunks.last() != Some(&chunk) {
+                used_chunks.push(chunk);
+            }
+            chunk += BYTES_IN_CHUNK;
+        }
+    });
+    used_chunks.sort_unstable();
+    used_chunks.dedup();
+
+    // The address ranges of the spaces.  A contiguous space owns its whole range.  The chunks of
+    // discontiguous spaces are shared, so we only consider the chunks that currently belong to a
+    // space.
+    let mut ranges: Vec<(Address, Address)> = vec![];
+    let mut has_discontiguous_space = false;
+    mmtk.get_plan().for_each_space(&mut |space| {
+        let common = space.common();
+        if common.contiguous {
+            ranges.push((common.start, common.start + common.extent));
+        } else {
+            has_discontiguous_space = true;
+        }
+    });
+    if has_discontiguous_space {
+        let layout = vm_layout();
+        let mut chunk = layout.heap_start;
+        while chunk < layout.heap_end {
+            let in_contiguous_space = ranges
+                .iter()
+                .any(|(start, end)| *start <= chunk && chunk < *end);
+            let in_space = ObjectReference::from_raw_address(chunk)
+                .is_some_and(memory_manager::is_in_mmtk_spaces);
+            if !in_contiguous_space && in_space {
+                match ranges.last_mut() {
+                    Some((_, end)) if *end == chunk => *end = chunk + BYTES_IN_CHUNK,
+                    _ => ranges.push((chunk, chunk + BYTES_IN_CHUNK)),
+                }
+            }
+            chunk += BYTES_IN_CHUNK;
+        }
+    }
+
+    let resident_before = resident_bytes();
+    for (start, end) in ranges {
+        let first = used_chunks.partition_point(|chunk| *chunk < start);
+        let mut free_start = start;
+        for chunk in used_chunks[first..]
+            .iter()
+            .take_while(|chunk| **chunk < end)
+        {
+            discard_pages(free_start, *chunk);
+            free_start = *chunk + BYTES_IN_CHUNK;
+        }
+        discard_pages(free_start, end);
+    }
+    let released = resident_before.saturating_sub(resident_bytes());
+    log::debug!("Released {} bytes of free chunks", released);
+    released
+}
+
+#[cfg(not(feature = "vo_bit"))]
+fn release_free_chunks<const COMPRESSED: bool>() -> usize {
+    0
+}
+
+/// Tell the OS that it can discard the pages in `[start, end)`.
+#[cfg(feature = "vo_bit")]
+fn discard_pages(start: Address, end: Address) {
+    if start >= end {
+        return;
+    }
+    // Parts of the range may not be mapped yet.  The kernel still discards the mapped pages and
+    // then fails with ENOMEM, which we ignore.
+    unsafe {
+        libc::madvise(start.to_mut_ptr(), end - start, libc::MADV_DONTNEED);
+    }
+}
+
+/// The resident set size of the process in bytes, or 0 if it is unknown
+#[cfg(feature = "vo_bit")]
+fn resident_bytes() -> usize {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    std::fs::read_to_string("/proc/self/statm")
+        .ok()
+        .and_then(|statm| statm.split_whitespace().nth(1)?.parse::<usize>().ok())
+        .map_or(0, |pages| pages * page_size)
+}
+
+/// Perform a periodic GC if the VM has been idle for the interval.  Returns the time in
+/// milliseconds until the next poll, or 0 if periodic GC is disabled.
+pub fn poll<const COMPRESSED: bool>(tls: VMMutatorThread) -> u64 {
+    let interval_ms = periodic_gc_interval();
+    if interval_ms == 0 {
+        return 0;
+    }
+    let interval = interval_ms * NANOS_PER_MILLI;
+    let mmtk = crate::singleton::<COMPRESSED>();
+
+    let last_gc = gc_stats::last_gc_stats();
+    let last_activity = last_gc
+        .map_or(0, |stats| stats.pause_end)
+        .max(ENABLED_SINCE.load(Ordering::Relaxed));
+    let idle = nanos_now().saturating_sub(last_activity);
+    if idle < interval {
+        return ((interval - idle) / NANOS_PER_MILLI).max(1);
+    }
+
+    // If the last GC was a periodic GC and nothing has been allocated since, another GC would not
+    // free anything.
+    if let Some(stats) = last_gc {
+        if stats.cause == GCCause::Periodic
+            && memory_manager::used_bytes(mmtk) <= stats.used_bytes_after
+        {
+            return interval_ms;
+        }
+    }
+
+    log::debug!(
+        "Requesting a periodic GC after {} ms idle",
+        idle / NANOS_PER_MILLI
+    );
+    PERIODIC_GC_REQUESTED.store(true, Ordering::Relaxed);
+    memory_manager::handle_user_collection_request(mmtk, tls);
+    // In case the GC did not happen, e.g. because user-requested GCs are ignored.
+    PERIODIC_GC_REQUESTED.store(false, Ordering::Relaxed);
+
+    if let Some(stats) = gc_stats::last_gc_stats() {
+        if stats.cause == GCCause::Periodic && last_gc.map(|s| s.gc_id) != Some(stats.gc_id) {
+            log::info!(
+                "Periodic GC {}: committed bytes decreased by {}, released {} bytes",
+                stats.gc_id,
+                stats.committed_bytes_decrease,
+                stats.released_bytes
+            );
+        }
+    }
+    interval_ms
+}
@@ -0,0 +1,190 @@
+//! Allocation-site pretenuring for generational plans.
+//!
+//! The VM may pass an allocation-site identifier to `mmtk_select_allocation_semantics` and
+//! `alloc`.  The OpenJDK binding uses the klass of the object as its site.  For each site, we track
+//! a sample of the objects allocated in the nursery, and check whether they survive the next GC.
+//! Once we have seen enough samples of a site, and the survival rate of the site exceeds the
+//! threshold, objects of the site are allocated with `AllocationSemantics::NonMoving`.  The
+//! non-moving space is a mature space, so nursery GCs never copy these objects.
+//!
+//! A pretenured site is re-evaluated after `PRETENURE_GCS` GCs: its decision is reset, and its
+//! objects are allocated and tracked in the nursery again.  The statistics of a site that stays
+//! below the threshold for `RESET_AFTER_GCS` GCs are reset, so old observations do not decide the
+//! fate of a site forever.
+//!
+//! Mutators never take a lock here.  Sites are hashed into fixed-size tables of atomics, which hold
+//! the allocation count, the survival statistics and the pretenured site of each slot.  Sites that
+//! share a slot share the allocation count and the statistics, and only one of them can be
+//! pretenured at a time.  The statistics are only used by GC.
+//!
+//! Only allocations that pass a site are affected.  The inline allocation fast path of the VM never
+//! consults the pretenured sites, so it always allocates in the nursery.
+//!
+//! Pretenuring is disabled if the threshold is 0, or if the plan is not generational.
+
+use mmtk::util::{Address, ObjectReference};
+use mmtk::AllocationSemantics;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An allocation-site identifier chosen by the VM.  `NO_SITE` means the site is unknown.
+pub type AllocationSite = usize;
+pub const NO_SITE: AllocationSite = 0;
+
+/// Track one in this many nursery allocations of each site.
+const TRACK_EVERY: usize = 16;
+/// The number of tracked objects of a site we need to see before deciding to pretenure it
+const MIN_OBSERVATIONS: usize = 64;
+/// The maximum number of objects tracked at a time
+const MAX_TRACKED_OBJECTS: usize = 4096;
+/// The number of slots in the site tables
+const LOG_SITE_TABLE_SIZE: usize = 12;
+const SITE_TABLE_SIZE: usize = 1 << LOG_SITE_TABLE_SIZE;
+/// The number of GCs after which a pretenured site is re-evaluated
+const PRETENURE_GCS: usize = 64;
+/// The number of GCs with enough observations below the threshold after which we reset the
+/// statistics of a site
+const RESET_AFTER_GCS: usize = 8;
+
+/// The survival rate (in percent) above which a site is pretenured, or 0 if pretenuring is
+/// disabled
+static PRETENURE_THRESHOLD: AtomicUsize = AtomicUsize::new(0);
+
+// Only used to initialize the tables below.
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of nursery allocations of the sites in each slot
+static ALLOCATION_COUNTS: [AtomicUsize; SITE_TABLE_SIZE] = [ZERO; SITE_TABLE_SIZE];
+/// The pretenured site in each slot, or `NO_SITE`
+static PRETENURED_SITES: [AtomicUsize; SITE_TABLE_SIZE] = [ZERO; SITE_TABLE_SIZE];
+/// The number of tracked objects of the sites in each slot that survived or died
+static SURVIVED: [AtomicUsize; SITE_TABLE_SIZE] = [ZERO; SITE_TABLE_SIZE];
+static DIED: [AtomicUsize; SITE_TABLE_SIZE] = [ZERO; SITE_TABLE_SIZE];
+/// For a pretenured slot, the GCs since it was pretenured.  Otherwise, the GCs in which it had
+/// enough observations, but was below the threshold.
+static SLOT_GCS: [AtomicUsize; SITE_TABLE_SIZE] = [ZERO; SITE_TABLE_SIZE];
+
+/// Objects allocated in the nursery since the last GC, and their sites.  Mutators claim an entry
+/// by incrementing `NUM_TRACKED`.
+static TRACKED_OBJECTS: [AtomicUsize; MAX_TRACKED_OBJECTS] = [ZERO; MAX_TRACKED_OBJECTS];
+static TRACKED_SITES: [AtomicUsize; MAX_TRACKED_OBJECTS] = [ZERO; MAX_TRACKED_OBJECTS];
+static NUM_TRACKED: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the survival rate (in percent) above which sites are pretenured.  0 disables pretenuring.
+pub fn set_pretenure_threshold(percent: usize) {
+    PRETENURE_THRESHOLD.store(percent.min(100), Ordering::Relaxed);
+}
+
+fn is_enabled(site: AllocationSite, semantics: AllocationSemantics) -> bool {
+    site != NO_SITE
+        && matches!(semantics, AllocationSemantics::Default)
+        && PRETENURE_THRESHOLD.load(Ordering::Relaxed) != 0
+        && crate::api::mmtk_is_generational_plan()
+}
+
+/// The slot of `site` in the site tables
+fn site_slot(site: AllocationSite) -> usize {
+    // Fibonacci hashing.  Sites are usually aligned addresses.
+    ((site as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (64 - LOG_SITE_TABLE_SIZE)) as usize
+}
+
+/// Return the semantics to allocate an object of `site` with.  A GC in the allocation slow path
+/// may pretenure the site, so the VM must select the semantics once and use the result for both
+/// `alloc` and `post_alloc` of the object.
+pub(crate) fn select_semantics(
+    site: AllocationSite,
+    semantics: AllocationSemantics,
+) -> AllocationSemantics {
+    if is_enabled(site, semantics)
+        && PRETENURED_SITES[site_slot(site)].load(Ordering::Relaxed) == site
+    {
+        AllocationSemantics::NonMoving
+    } else {
+        semantics
+    }
+}
+
+/// Called after allocating `addr` for `site` in the nursery.
+pub(crate) fn on_nursery_alloc(
+    site: AllocationSite,
+    semantics: AllocationSemantics,
+    addr: Address,
+) {
+    if addr.is_zero() || !is_enabled(site, semantics) {
+        return;
+    }
+    let allocations = ALLOCATION_COUNTS[site_slot(site)].fetch_add(1, Ordering::Relaxed) + 1;
+    if allocations % TRACK_EVERY != 0 {
+        return;
+    }
+    let index = NUM_TRACKED.fetch_add(1, Ordering::Relaxed);
+    if index < MAX_TRACKED_OBJECTS {
+        // The object is not initialized yet, but it will be by the next GC.  GC cannot happen
+        // before we store the entry, because the mutator is still in `alloc`.
+        TRACKED_OBJECTS[index].store(addr.as_usize(), Ordering::Relaxed);
+        TRACKED_SITES[index].store(site, Ordering::Relaxed);
+    }
+}
+
+/// Called during GC after the transitive closure.  Updates the survival statistics with the
+/// tracked objects, and decides which sites to pretenure.
+pub(crate) fn process_tracked_objects() {
+    let threshold = PRETENURE_THRESHOLD.load(Ordering::Relaxed);
+    let num_tracked = NUM_TRACKED
+        .swap(0, Ordering::Relaxed)
+        .min(MAX_TRACKED_OBJECTS);
+    for index in 0..num_tracked {
+        let addr = unsafe { Address::from_usize(TRACKED_OBJECTS[index].load(Ordering::Relaxed)) };
+        let object = ObjectReference::from_raw_address(addr).unwrap();
+        let site = TRACKED_SITES[index].load(Ordering::Relaxed);
+        let slot = site_slot(site);
+        // Survivors are promoted to the mature space, so we stop tracking them, too.
+        if object.is_reachable() {
+            SURVIVED[slot].fetch_add(1, Ordering::Relaxed);
+        } else {
+            DIED[slot].fetch_add(1, Ordering::Relaxed);
+        }
+        let survived = SURVIVED[slot].load(Ordering::Relaxed);
+        let observed = survived + DIED[slot].load(Ordering::Relaxed);
+        if PRETENURED_SITES[slot].load(Ordering::Relaxed) == NO_SITE
+            && threshold != 0
+            && observed >= MIN_OBSERVATIONS
+            && survived * 100 > threshold * observed
+        {
+            PRETENURED_SITES[slot].store(site, Ordering::Relaxed);
+            SLOT_GCS[slot].store(0, Ordering::Relaxed);
+            log::info!(
+                "Pretenuring allocation site {:#x}: {} of {} sampled objects survived",
+                site,
+                survived,
+                observed
+            );
+        }
+    }
+    age_slots(threshold);
+}
+
+/// Count one more GC for each slot, and reset the slots whose decision or statistics expired.
+fn age_slots(threshold: usize) {
+    for slot in 0..SITE_TABLE_SIZE {
+        let site = PRETENURED_SITES[slot].load(Ordering::Relaxed);
+        let expired = if site != NO_SITE {
+            SLOT_GCS[slot].fetch_add(1, Ordering::Relaxed) + 1 >= PRETENURE_GCS
+        } else {
+            let survived = SURVIVED[slot].load(Ordering::Relaxed);
+            let observed = survived + DIED[slot].load(Ordering::Relaxed);
+            observed >= MIN_OBSERVATIONS
+                && survived * 100 <= threshold * observed
+                && SLOT_GCS[slot].fetch_add(1, Ordering::Relaxed) + 1 >= RESET_AFTER_GCS
+        };
+        if expired {
+            if site != NO_SITE {
+                log::debug!("Re-evaluating pretenured allocation site {:#x}", site);
+            }
+            PRETENURED_SITES[slot].store(NO_SITE, Ordering::Relaxed);
+            SURVIVED[slot].store(0, Ordering::Relaxed);
+            DIED[slot].store(0, Ordering::Relaxed);
+            SLOT_GCS[slot].store(0, Ordering::Relaxed);
+        }
+    }
+}
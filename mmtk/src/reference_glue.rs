@@ -1,7 +1,10 @@
 use crate::abi::{InstanceRefKlass, Oop};
+use crate::upcalls;
 use crate::OpenJDK;
-use crate::UPCALLS;
+use mmtk::scheduler::ProcessEdgesWork;
+use mmtk::util::finalizable_processor::Finalizable;
 use mmtk::util::opaque_pointer::VMWorkerThread;
+use mmtk::util::options::PlanSelector;
 use mmtk::util::ObjectReference;
 use mmtk::vm::slot::Slot;
 use mmtk::vm::ReferenceGlue;
@@ -9,7 +12,7 @@ use mmtk::vm::ReferenceGlue;
 pub struct VMReferenceGlue {}
 
 impl<const COMPRESSED: bool> ReferenceGlue<OpenJDK<COMPRESSED>> for VMReferenceGlue {
-    type FinalizableType = ObjectReference;
+    type FinalizableType = FinalReference;
 
     fn set_referent(reff: ObjectReference, referent: ObjectReference) {
         let oop = Oop::from(reff);
@@ -20,12 +23,106 @@ impl<const COMPRESSED: bool> ReferenceGlue<OpenJDK<COMPRESSED>> for VMReferenceG
         InstanceRefKlass::referent_address::<COMPRESSED>(oop).load()
     }
     fn enqueue_references(references: &[ObjectReference], _tls: VMWorkerThread) {
-        unsafe {
-            ((*UPCALLS).enqueue_references)(references.as_ptr(), references.len());
-        }
+        crate::gc_stats::count_enqueued_references(references.len());
+        (upcalls().enqueue_references)(references.as_ptr(), references.len());
     }
     fn clear_referent(new_reference: ObjectReference) {
         let oop = Oop::from(new_reference);
         InstanceRefKlass::referent_address::<COMPRESSED>(oop).store_null();
     }
 }
+
+/// An entry of the finalizable processor of mmtk-core: a `java.lang.ref.FinalReference`
+/// discovered during tracing, and its referent.
+///
+/// Finalizable objects are registered as in OpenJDK's built-in collectors: the VM calls
+/// `Finalizer.register`, which creates a `java.lang.ref.Finalizer` for the object.  When the
+/// referent becomes unreachable, the processor resurrects it, and hands the reference to the MMTk
+/// Finalizer Thread (see `get_finalized_object`), which puts it into the reference pending list.
+/// `java.lang.ref.Finalizer` then runs the finalizer.
+#[derive(Clone, Copy, Debug)]
+pub struct FinalReference {
+    reference: ObjectReference,
+    referent: ObjectReference,
+}
+
+impl FinalReference {
+    pub fn reference(&self) -> ObjectReference {
+        self.reference
+    }
+}
+
+impl Finalizable for FinalReference {
+    fn get_reference(&self) -> ObjectReference {
+        self.referent
+    }
+
+    fn set_reference(&mut self, object: ObjectReference) {
+        self.referent = object;
+    }
+
+    fn keep_alive<E: ProcessEdgesWork>(&mut self, trace: &mut E) {
+        // The `referent` field of a discovered `FinalReference` is not visited when the reference
+        // is scanned, so we update it here.  `reference` has been copied (if at all) by
+        // `trace_object`, so we update the new copy.
+        self.reference = trace.trace_object(self.reference);
+        self.referent = trace.trace_object(self.referent);
+        let oop = Oop::from(self.reference);
+        if crate::use_compressed_oops() {
+            InstanceRefKlass::referent_address::<true>(oop).store(self.referent);
+        } else {
+            InstanceRefKlass::referent_address::<false>(oop).store(self.referent);
+        }
+    }
+}
+
+/// Whether `FinalReference` instances are discovered.
+///
+/// With `no_finalizer`, mmtk-core does not process finalizable objects, so nothing would keep the
+/// referent of a registered reference alive.  MarkCompact computes the new addresses of objects
+/// in a second trace before it moves them, so `keep_alive` cannot tell where to write the
+/// referent.  In both cases, we treat `FinalReference` as strong.
+fn should_discover_final_references<const COMPRESSED: bool>() -> bool {
+    let options = crate::singleton::<COMPRESSED>().get_options();
+    !*options.no_finalizer && *options.plan != PlanSelector::MarkCompact
+}
+
+/// Discover the `FinalReference` `oop` when it is scanned.  Returns true if its referent must not
+/// be traced, i.e. the reference is (or has been) registered with the finalizable processor.
+/// Otherwise, the caller should process the reference as strong.
+///
+/// Like OpenJDK's built-in collectors, we only discover active references, i.e. those whose
+/// `next` field is null.  A `FinalReference` whose referent became unreachable is put into the
+/// reference pending list with `next` pointing to itself, and its referent stays strongly
+/// reachable until `java.lang.ref.Finalizer` has run the finalizer and cleared it.
+///
+/// An active reference stays registered until its referent becomes unreachable.  We mark it by
+/// pointing its `discovered` field to itself so that we do not register it again in later GCs.
+/// The finalizer thread overwrites `discovered` when it enqueues the reference.
+pub(crate) fn discover_final_reference<const COMPRESSED: bool>(oop: Oop) -> bool {
+    if !should_discover_final_references::<COMPRESSED>()
+        || InstanceRefKlass::next_address::<COMPRESSED>(oop)
+            .load()
+            .is_some()
+    {
+        return false;
+    }
+    let Some(referent) = InstanceRefKlass::referent_address::<COMPRESSED>(oop).load() else {
+        return false;
+    };
+    let discovered = InstanceRefKlass::discovered_address::<COMPRESSED>(oop);
+    if discovered.load().is_some() {
+        return true;
+    }
+    let reference = ObjectReference::from(oop);
+    discovered.store(reference);
+    crate::gc_stats::count_registered_finalizer();
+    mmtk::memory_manager::add_finalizer(
+        crate::singleton::<COMPRESSED>(),
+        FinalReference {
+            reference,
+            referent,
+        },
+    );
+    true
+}
@@ -0,0 +1,140 @@
+//! Enumerate GC roots outside of GC work packets, e.g. for heap dumps and heap verification.
+//!
+//! We use the same upcalls as root scanning in `gc_work.rs`, but collect the root slots of each
+//! category into a vector instead of creating work packets.  All mutators must be stopped.
+
+use crate::active_plan::VMActivePlan;
+use crate::scanning::new_slots_buffer;
+use crate::{upcalls, MATURE_CODE_CACHE_ROOTS, NURSERY_CODE_CACHE_ROOTS};
+use crate::{NewBuffer, OpenJDK, OpenJDKSlot, SlotsClosure};
+use mmtk::util::opaque_pointer::VMMutatorThread;
+use mmtk::util::Address;
+use mmtk::vm::ActivePlan;
+use mmtk::MutatorContext;
+use std::fmt;
+
+/// The category of a root slot, named after the upcall that reports it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RootCategory {
+    Universe,
+    /// JNI global references.  JNI weak global references are in `WeakProcessor`.
+    JNIHandles,
+    ObjectSynchronizer,
+    Management,
+    JvmtiExport,
+    AOTLoader,
+    SystemDictionary,
+    /// Interned strings.  They are weak roots during GC (see `weak_roots.rs`), but we still check
+    /// them when verifying the heap.
+    StringTable,
+    ClassLoaderDataGraph,
+    /// The weak `OopStorage`s, e.g. JNI weak global references.  Like `StringTable`, they are weak
+    /// roots during GC.
+    WeakProcessor,
+    VMThread,
+    CodeCache,
+    /// The stack and the thread-local roots of the `index`-th mutator.
+    Mutator {
+        index: usize,
+        tls: VMMutatorThread,
+    },
+}
+
+impl RootCategory {
+    /// Whether the roots of this category do not keep their objects alive
+    pub(crate) fn is_weak(&self) -> bool {
+        matches!(
+            self,
+            RootCategory::StringTable | RootCategory::WeakProcessor
+        )
+    }
+}
+
+impl fmt::Display for RootCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RootCategory::Mutator { index, tls } => write!(f, "Mutator #{index} ({tls:?})"),
+            _ => write!(f, "{self:?}"),
+        }
+    }
+}
+
+/// Collects the root slots reported by a `scan_*_roots` upcall.
+extern "C" fn collect_slots<const COMPRESSED: bool>(
+    ptr: *mut Address,
+    length: usize,
+    capacity: usize,
+    slots_ptr: *mut libc::c_void,
+) -> NewBuffer {
+    if !ptr.is_null() {
+        let buf =
+            unsafe { Vec::<OpenJDKSlot<COMPRESSED>>::from_raw_parts(ptr as _, length, capacity) };
+        let slots: &mut Vec<OpenJDKSlot<COMPRESSED>> =
+            unsafe { &mut *(slots_ptr as *mut Vec<OpenJDKSlot<COMPRESSED>>) };
+        slots.extend(buf);
+    }
+    new_slots_buffer()
+}
+
+fn scan_roots<const COMPRESSED: bool>(
+    scan: impl FnOnce(SlotsClosure),
+) -> Vec<OpenJDKSlot<COMPRESSED>> {
+    let mut slots: Vec<OpenJDKSlot<COMPRESSED>> = vec![];
+    scan(SlotsClosure {
+        func: collect_slots::<COMPRESSED>,
+        data: &mut slots as *mut Vec<OpenJDKSlot<COMPRESSED>> as *mut libc::c_void,
+    });
+    slots
+}
+
+/// Call `f` with the root slots of each category.
+pub(crate) fn for_each_root_category<const COMPRESSED: bool>(
+    mut f: impl FnMut(RootCategory, Vec<OpenJDKSlot<COMPRESSED>>),
+) {
+    let upcalls = upcalls();
+    let categories: [(RootCategory, extern "C" fn(SlotsClosure)); 11] = [
+        (RootCategory::Universe, upcalls.scan_universe_roots),
+        (RootCategory::JNIHandles, upcalls.scan_jni_handle_roots),
+        (
+            RootCategory::ObjectSynchronizer,
+            upcalls.scan_object_synchronizer_roots,
+        ),
+        (RootCategory::Management, upcalls.scan_management_roots),
+        (RootCategory::JvmtiExport, upcalls.scan_jvmti_export_roots),
+        (RootCategory::AOTLoader, upcalls.scan_aot_loader_roots),
+        (
+            RootCategory::SystemDictionary,
+            upcalls.scan_system_dictionary_roots,
+        ),
+        (RootCategory::StringTable, upcalls.scan_string_table_roots),
+        (
+            RootCategory::ClassLoaderDataGraph,
+            upcalls.scan_class_loader_data_graph_roots,
+        ),
+        (
+            RootCategory::WeakProcessor,
+            upcalls.scan_weak_processor_roots,
+        ),
+        (RootCategory::VMThread, upcalls.scan_vm_thread_roots),
+    ];
+    for (category, scan) in categories {
+        f(category, scan_roots::<COMPRESSED>(scan));
+    }
+
+    // Code cache roots are remembered by the binding.  See `ScanCodeCacheRoots`.
+    let mut code_cache_slots: Vec<OpenJDKSlot<COMPRESSED>> = vec![];
+    for roots in [&*NURSERY_CODE_CACHE_ROOTS, &*MATURE_CODE_CACHE_ROOTS] {
+        let roots = roots.lock().unwrap();
+        code_cache_slots.extend(roots.values().flatten().map(|a| OpenJDKSlot::from(*a)));
+    }
+    f(RootCategory::CodeCache, code_cache_slots);
+
+    let mutators = <VMActivePlan as ActivePlan<OpenJDK<COMPRESSED>>>::mutators();
+    for (index, mutator) in mutators.enumerate() {
+        let tls = mutator.get_tls();
+        let slots = scan_roots::<COMPRESSED>(|closure| {
+            (upcalls().scan_roots_in_mutator_thread)(closure, tls)
+        });
+        f(RootCategory::Mutator { index, tls }, slots);
+    }
+}
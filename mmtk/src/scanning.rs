@@ -1,12 +1,12 @@
 use crate::gc_work::*;
 use crate::Slot;
-use crate::{NewBuffer, OpenJDKSlot, UPCALLS};
+use crate::{upcalls, NewBuffer, OpenJDKSlot};
 use crate::{OpenJDK, SlotsClosure};
 use mmtk::memory_manager;
-use mmtk::scheduler::WorkBucketStage;
+use mmtk::scheduler::{GCWorker, WorkBucketStage};
 use mmtk::util::opaque_pointer::*;
 use mmtk::util::{Address, ObjectReference};
-use mmtk::vm::{RootsWorkFactory, Scanning, SlotVisitor};
+use mmtk::vm::{ObjectTracerContext, RootsWorkFactory, Scanning, SlotVisitor};
 use mmtk::Mutator;
 use mmtk::MutatorContext;
 
@@ -27,6 +27,11 @@ extern "C" fn report_slots_and_renew_buffer<S: Slot, F: RootsWorkFactory<S>>(
         let factory: &mut F = unsafe { &mut *(factory_ptr as *mut F) };
         factory.create_process_roots_work(buf);
     }
+    new_slots_buffer()
+}
+
+/// Allocate an empty buffer for the C++ code to report root slots into.
+pub(crate) fn new_slots_buffer() -> NewBuffer {
     let (ptr, _, capacity) = {
         // TODO: Use Vec::into_raw_parts() when the method is available.
         use std::mem::ManuallyDrop;
@@ -64,9 +69,9 @@ impl<const COMPRESSED: bool> Scanning<OpenJDK<COMPRESSED>> for VMScanning {
         mut factory: impl RootsWorkFactory<OpenJDKSlot<COMPRESSED>>,
     ) {
         let tls = mutator.get_tls();
-        unsafe {
-            ((*UPCALLS).scan_roots_in_mutator_thread)(to_slots_closure(&mut factory), tls);
-        }
+        crate::gc_stats::time_root_scanning(|| {
+            (upcalls().scan_roots_in_mutator_thread)(to_slots_closure(&mut factory), tls);
+        });
     }
 
     fn scan_vm_specific_roots(
@@ -85,9 +90,7 @@ impl<const COMPRESSED: bool> Scanning<OpenJDK<COMPRESSED>> for VMScanning {
                 Box::new(ScanAOTLoaderRoots::new(factory.clone())) as _,
                 Box::new(ScanSystemDictionaryRoots::new(factory.clone())) as _,
                 Box::new(ScanCodeCacheRoots::new(factory.clone())) as _,
-                Box::new(ScanStringTableRoots::new(factory.clone())) as _,
                 Box::new(ScanClassLoaderDataGraphRoots::new(factory.clone())) as _,
-                Box::new(ScanWeakProcessorRoots::new(factory.clone())) as _,
                 Box::new(ScanVMThreadRoots::new(factory)) as _,
             ],
         );
@@ -98,8 +101,27 @@ impl<const COMPRESSED: bool> Scanning<OpenJDK<COMPRESSED>> for VMScanning {
     }
 
     fn prepare_for_roots_re_scanning() {
-        unsafe {
-            ((*UPCALLS).prepare_for_roots_re_scanning)();
-        }
+        (upcalls().prepare_for_roots_re_scanning)();
+    }
+
+    fn process_weak_refs(
+        _worker: &mut GCWorker<OpenJDK<COMPRESSED>>,
+        _tracer_context: impl ObjectTracerContext<OpenJDK<COMPRESSED>>,
+    ) -> bool {
+        // Objects tracked for pretenuring are weakly referenced.  See `pretenure.rs`.
+        crate::pretenure::process_tracked_objects();
+        crate::weak_roots::schedule_weak_roots_processing::<COMPRESSED>(
+            WorkBucketStage::VMRefClosure,
+        );
+        false
+    }
+
+    fn forward_weak_refs(
+        _worker: &mut GCWorker<OpenJDK<COMPRESSED>>,
+        _tracer_context: impl ObjectTracerContext<OpenJDK<COMPRESSED>>,
+    ) {
+        crate::weak_roots::schedule_weak_roots_processing::<COMPRESSED>(
+            WorkBucketStage::VMRefForwarding,
+        );
     }
 }
@@ -1,4 +1,5 @@
 use std::{
+    mem,
     ops::Range,
     sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
@@ -23,9 +24,8 @@ pub fn enable_compressed_oops() {
         !COMPRESSED_OOPS_INITIALIZED.fetch_or(true, Ordering::Relaxed),
         "cannot enable compressed pointers twice."
     );
-    if cfg!(not(target_arch = "x86_64")) {
-        panic!("Compressed pointer is only enable on x86_64 platforms.\
-            For other RISC architectures, we need to find a way to process compressed embeded pointers in code objects first.");
+    if cfg!(not(target_pointer_width = "64")) {
+        panic!("Compressed pointers are only supported on 64-bit platforms.");
     }
     USE_COMPRESSED_OOPS.store(true, Ordering::Relaxed)
 }
@@ -36,21 +36,35 @@ pub fn use_compressed_oops() -> bool {
 }
 
 /// Set compressed pointer base and shift based on heap range
+///
+/// Like HotSpot, the shift is either 0 or log2 of `ObjectAlignmentInBytes`.  The compiled code
+/// generated by HotSpot assumes that.
 pub fn initialize_compressed_oops_base_and_shift() {
-    let heap_end = mmtk::memory_manager::last_heap_address().as_usize();
-    if heap_end <= (4usize << 30) {
-        BASE.store(Address::ZERO, Ordering::Relaxed);
-        SHIFT.store(0, Ordering::Relaxed);
-    } else if heap_end <= (32usize << 30) {
-        BASE.store(Address::ZERO, Ordering::Relaxed);
-        SHIFT.store(3, Ordering::Relaxed);
+    let (base, shift) = compressed_oops_base_and_shift(
+        mmtk::memory_manager::starting_heap_address(),
+        mmtk::memory_manager::last_heap_address(),
+        crate::object_model::log_object_alignment(),
+    );
+    BASE.store(base, Ordering::Relaxed);
+    SHIFT.store(shift, Ordering::Relaxed);
+}
+
+/// If the base is not zero, it is this many bytes below the start of the heap, so that no object
+/// is encoded as 0, which is null.
+pub(crate) const HEAP_BASE_GAP: usize = 4096;
+
+/// The base and the shift of compressed oops for the heap range `[heap_start, heap_end)`
+pub(crate) fn compressed_oops_base_and_shift(
+    heap_start: Address,
+    heap_end: Address,
+    log_alignment: usize,
+) -> (Address, usize) {
+    if heap_end.as_usize() <= (4usize << 30) {
+        (Address::ZERO, 0)
+    } else if heap_end.as_usize() <= ((4usize << 30) << log_alignment) {
+        (Address::ZERO, log_alignment)
     } else {
-        // set heap base as HEAP_START - 4096, to make sure null pointer value does not conflict with HEAP_START
-        BASE.store(
-            mmtk::memory_manager::starting_heap_address() - 4096,
-            Ordering::Relaxed,
-        );
-        SHIFT.store(3, Ordering::Relaxed);
+        (heap_start - HEAP_BASE_GAP, log_alignment)
     }
 }
 
@@ -63,9 +77,18 @@ pub fn initialize_compressed_oops_base_and_shift() {
 /// * If this is a field of an object, the slot is compressed.
 /// * If this is a root pointer: The c++ part of the binding should pass all the root pointers to
 ///   rust as tagged pointers.
-///   * If the 63rd bit of the pointer is set to 1, the value referenced by the pointer is a
-///     32-bit compressed integer.
-///   * Otherwise, it is a uncompressed root pointer.
+///   * If the 63rd bit of the pointer is set to 1, the value referenced by the pointer is an
+///     uncompressed 64-bit pointer.
+///   * Otherwise, it is a 32-bit compressed integer.
+///
+///   User-space addresses never have bit 63 set on x86_64, AArch64 or RISC-V, so the tag does not
+///   depend on the architecture.
+///
+/// Oops embedded in compiled code (nmethods) are reported as root slots, too.  On x86, they are
+/// immediates in the instructions, and may be unaligned.  On RISC architectures (AArch64, RISC-V),
+/// the instructions materialize oops, compressed or not, from the uncompressed oop table of the
+/// nmethod.  We report and update the table entries, and the c++ part of the binding patches the
+/// instructions from the table after GC.  See `mmtk_resume_mutators`.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 #[repr(transparent)]
 pub struct OpenJDKSlot<const COMPRESSED: bool> {
@@ -93,42 +116,63 @@ impl<const COMPRESSED: bool> OpenJDKSlot<COMPRESSED> {
         unsafe { Address::from_usize(self.addr.as_usize() << 1 >> 1) }
     }
 
-    fn x86_read_unaligned<T, const UNTAG: bool>(&self) -> T {
-        debug_assert!(cfg!(any(target_arch = "x86", target_arch = "x86_64")));
-        // Workaround: On x86 (including x86_64), machine instructions may contain pointers as
-        // immediates, and they may be unaligned.  It is an undefined behavior in Rust to
-        // dereference unaligned pointers.  We have to explicitly use unaligned memory access
-        // methods.  On x86, ordinary MOV instructions can load and store memory at unaligned
-        // addresses, so we expect `ptr.read_unaligned()` to have no performance penalty over
-        // `ptr.read()` if `ptr` is actually aligned.
-        unsafe {
-            let slot = if UNTAG {
-                self.untagged_address()
-            } else {
+    /// The address of the value, with the tag stripped if `UNTAG`
+    fn value_address<const UNTAG: bool>(&self) -> Address {
+        if UNTAG {
+            self.untagged_address()
+        } else {
+            self.addr
+        }
+    }
+
+    fn read<T, const UNTAG: bool>(&self) -> T {
+        let addr = self.value_address::<UNTAG>();
+        let ptr = addr.to_ptr::<T>();
+        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+            // Workaround: On x86 (including x86_64), machine instructions may contain pointers as
+            // immediates, and they may be unaligned.  It is an undefined behavior in Rust to
+            // dereference unaligned pointers.  We have to explicitly use unaligned memory access
+            // methods.  On x86, ordinary MOV instructions can load and store memory at unaligned
+            // addresses, so we expect `ptr.read_unaligned()` to have no performance penalty over
+            // `ptr.read()` if `ptr` is actually aligned.
+            unsafe { ptr.read_unaligned() }
+        } else {
+            // Other architectures never embed pointers in instructions, so slots are aligned.
+            debug_assert!(
+                addr.is_aligned_to(mem::align_of::<T>()),
+                "unaligned slot {:?}",
                 self.addr
-            };
-            let ptr = slot.to_ptr::<T>();
-            ptr.read_unaligned()
+            );
+            unsafe { ptr.read() }
         }
     }
 
-    fn x86_write_unaligned<T: Copy, const UNTAG: bool>(&self, v: T) {
-        debug_assert!(cfg!(any(target_arch = "x86", target_arch = "x86_64")));
-        unsafe {
-            let slot = if UNTAG {
-                self.untagged_address()
-            } else {
+    fn write<T, const UNTAG: bool>(&self, v: T) {
+        let addr = self.value_address::<UNTAG>();
+        let ptr = addr.to_mut_ptr::<T>();
+        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+            unsafe { ptr.write_unaligned(v) }
+        } else {
+            debug_assert!(
+                addr.is_aligned_to(mem::align_of::<T>()),
+                "unaligned slot {:?}",
                 self.addr
-            };
-            let ptr = slot.to_mut_ptr::<T>();
-            ptr.write_unaligned(v)
+            );
+            unsafe { ptr.write(v) }
         }
     }
 
     /// encode an object pointer to its u32 compressed form
     fn compress(o: ObjectReference) -> u32 {
-        ((o.to_raw_address() - BASE.load(Ordering::Relaxed)) >> SHIFT.load(Ordering::Relaxed))
-            as u32
+        let offset = o.to_raw_address() - BASE.load(Ordering::Relaxed);
+        let shift = SHIFT.load(Ordering::Relaxed);
+        debug_assert_eq!(offset & ((1 << shift) - 1), 0, "{} is not aligned", o);
+        debug_assert!(
+            offset >> shift <= u32::MAX as usize,
+            "{} is out of range",
+            o
+        );
+        (offset >> shift) as u32
     }
 
     /// decode an object pointer from its u32 compressed form
@@ -148,57 +192,40 @@ impl<const COMPRESSED: bool> OpenJDKSlot<COMPRESSED> {
 
     /// Store a null reference in the slot.
     pub fn store_null(&self) {
-        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
-            if COMPRESSED {
-                if self.is_compressed() {
-                    self.x86_write_unaligned::<u32, true>(0)
-                } else {
-                    self.x86_write_unaligned::<Address, true>(Address::ZERO)
-                }
+        if COMPRESSED {
+            if self.is_compressed() {
+                self.write::<u32, true>(0)
             } else {
-                self.x86_write_unaligned::<Address, false>(Address::ZERO)
+                self.write::<Address, true>(Address::ZERO)
             }
         } else {
-            debug_assert!(!COMPRESSED);
-            unsafe { self.addr.store(0) }
+            self.write::<Address, false>(Address::ZERO)
         }
     }
 }
 
 impl<const COMPRESSED: bool> Slot for OpenJDKSlot<COMPRESSED> {
     fn load(&self) -> Option<ObjectReference> {
-        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
-            if COMPRESSED {
-                if self.is_compressed() {
-                    Self::decompress(self.x86_read_unaligned::<u32, true>())
-                } else {
-                    let addr = self.x86_read_unaligned::<Address, true>();
-                    ObjectReference::from_raw_address(addr)
-                }
+        if COMPRESSED {
+            if self.is_compressed() {
+                Self::decompress(self.read::<u32, true>())
             } else {
-                let addr = self.x86_read_unaligned::<Address, false>();
-                ObjectReference::from_raw_address(addr)
+                ObjectReference::from_raw_address(self.read::<Address, true>())
             }
         } else {
-            debug_assert!(!COMPRESSED);
-            unsafe { self.addr.load() }
+            ObjectReference::from_raw_address(self.read::<Address, false>())
         }
     }
 
     fn store(&self, object: ObjectReference) {
-        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
-            if COMPRESSED {
-                if self.is_compressed() {
-                    self.x86_write_unaligned::<u32, true>(Self::compress(object))
-                } else {
-                    self.x86_write_unaligned::<ObjectReference, true>(object)
-                }
+        if COMPRESSED {
+            if self.is_compressed() {
+                self.write::<u32, true>(Self::compress(object))
             } else {
-                self.x86_write_unaligned::<ObjectReference, false>(object)
+                self.write::<ObjectReference, true>(object)
             }
         } else {
-            debug_assert!(!COMPRESSED);
-            unsafe { self.addr.store(object) }
+            self.write::<ObjectReference, false>(object)
         }
     }
 }
@@ -0,0 +1,94 @@
+//! The clearing policy of soft references.
+//!
+//! MMTk keeps the referents of all the soft references it is given alive, except in emergency
+//! collections, where it clears them all.  Like HotSpot's `LRUMaxHeapPolicy`, we instead clear a
+//! soft reference if it has not been used for too long: `SoftReference.get()` sets the
+//! `timestamp` of the reference to `SoftReference.clock`, which the VM advances at the end of
+//! each GC.  A reference is cleared if its timestamp is more than `SoftRefLRUPolicyMSPerMB`
+//! milliseconds per megabyte of free heap (the max heap size minus the heap used after the
+//! previous GC) behind the clock.
+//!
+//! The decision is made when a soft reference is discovered.  References to be cleared are given
+//! to MMTk as weak references, and the others as soft references.  In emergency collections, we
+//! use HotSpot's `AlwaysClearPolicy` and clear all soft references before running out of memory.
+
+use crate::abi::Oop;
+use crate::upcalls;
+use mmtk::memory_manager;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+
+/// `SoftRefLRUPolicyMSPerMB`
+static MS_PER_MB: AtomicUsize = AtomicUsize::new(1000);
+/// The bytes used after the previous GC
+static USED_AT_LAST_GC: AtomicUsize = AtomicUsize::new(0);
+
+/// `SoftReference.clock` at the start of the current GC
+static TIMESTAMP_CLOCK: AtomicI64 = AtomicI64::new(0);
+/// References unused for longer than this (in milliseconds) are cleared in the current GC
+static MAX_INTERVAL: AtomicI64 = AtomicI64::new(0);
+/// Whether all soft references are cleared in the current GC
+static ALWAYS_CLEAR: AtomicBool = AtomicBool::new(false);
+
+const BYTES_IN_MB: usize = 1 << 20;
+
+/// Set `SoftRefLRUPolicyMSPerMB`.
+pub fn set_soft_ref_lru_policy_ms_per_mb(ms_per_mb: usize) {
+    MS_PER_MB.store(ms_per_mb, Ordering::Relaxed);
+}
+
+/// The time a soft reference may stay unused if `free_bytes` of the heap are free
+pub(crate) fn max_interval(free_bytes: usize, ms_per_mb: usize) -> i64 {
+    i64::try_from((free_bytes / BYTES_IN_MB).saturating_mul(ms_per_mb)).unwrap_or(i64::MAX)
+}
+
+/// Whether a soft reference with `timestamp` is cleared by the LRU policy
+pub(crate) fn lru_should_clear(timestamp_clock: i64, timestamp: i64, max_interval: i64) -> bool {
+    timestamp_clock.saturating_sub(timestamp) > max_interval
+}
+
+fn timestamp_offset() -> i32 {
+    lazy_static! {
+        static ref TIMESTAMP_OFFSET: i32 = (upcalls().soft_reference_timestamp_offset)();
+    }
+    *TIMESTAMP_OFFSET
+}
+
+/// Read `SoftReference.clock`.  The mirror of `SoftReference` may move, so we look it up each
+/// time.
+fn read_clock() -> i64 {
+    let mirror = Oop::from((upcalls().soft_reference_mirror)());
+    let offset = (upcalls().soft_reference_clock_offset)();
+    unsafe { mirror.get_field_address(offset).load::<i64>() }
+}
+
+/// Called when all mutators are stopped.  Choose the policy for the current GC.
+pub(crate) fn setup_policy<const COMPRESSED: bool>() {
+    let mmtk = crate::singleton::<COMPRESSED>();
+    ALWAYS_CLEAR.store(mmtk.is_emergency_collection(), Ordering::Relaxed);
+    let max_heap = crate::gc_trigger::max_heap_size(&mmtk.get_options().gc_trigger);
+    let free_bytes = max_heap.saturating_sub(USED_AT_LAST_GC.load(Ordering::Relaxed));
+    MAX_INTERVAL.store(
+        max_interval(free_bytes, MS_PER_MB.load(Ordering::Relaxed)),
+        Ordering::Relaxed,
+    );
+    TIMESTAMP_CLOCK.store(read_clock(), Ordering::Relaxed);
+}
+
+/// Called before the mutators are resumed.
+pub(crate) fn end_gc<const COMPRESSED: bool>() {
+    let used = memory_manager::used_bytes(crate::singleton::<COMPRESSED>());
+    USED_AT_LAST_GC.store(used, Ordering::Relaxed);
+}
+
+/// Whether the soft reference `oop` should be cleared if its referent is not strongly reachable
+pub(crate) fn should_clear(oop: Oop) -> bool {
+    if ALWAYS_CLEAR.load(Ordering::Relaxed) {
+        return true;
+    }
+    let timestamp = unsafe { oop.get_field_address(timestamp_offset()).load::<i64>() };
+    lru_should_clear(
+        TIMESTAMP_CLOCK.load(Ordering::Relaxed),
+        timestamp,
+        MAX_INTERVAL.load(Ordering::Relaxed),
+    )
+}
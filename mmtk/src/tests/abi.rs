@@ -0,0 +1,96 @@
+use super::mock_vm::*;
+use crate::abi::{check_memory_layouts, InstanceKlass, Klass, ReferenceType};
+use crate::KlassLayoutClosure;
+use std::ffi::CString;
+use std::{mem, ptr};
+
+/// A layout entry as the VM would report it
+type Entry = (&'static str, isize, Option<&'static Klass>);
+
+fn check<const COMPRESSED: bool>(entries: &[Entry]) -> Vec<String> {
+    check_memory_layouts::<COMPRESSED>(|closure: KlassLayoutClosure| {
+        for &(name, value, klass) in entries {
+            let name = CString::new(name).unwrap();
+            let klass = klass.map_or(ptr::null(), |k| k as *const Klass);
+            (closure.func)(name.as_ptr(), value, klass, closure.data);
+        }
+    })
+}
+
+/// The entries of the mock VM, which has the same layout as the binding
+fn mock_vm_entries<const COMPRESSED: bool>(klass: &'static Klass) -> Vec<Entry> {
+    let length_offset = if COMPRESSED { 12 } else { 16 };
+    let oop_maps_offset = mem::size_of::<InstanceKlass>() + (VTABLE_LEN + ITABLE_LEN) * 8;
+    vec![
+        ("sizeof(Klass)", mem::size_of::<Klass>() as isize, None),
+        ("oopDesc::klass_offset_in_bytes()", 8, None),
+        (
+            "arrayOopDesc::length_offset_in_bytes()",
+            length_offset,
+            None,
+        ),
+        (
+            "arrayOopDesc::base_offset_in_bytes(T_OBJECT)",
+            array_header_in_bytes::<COMPRESSED>() as isize,
+            None,
+        ),
+        (
+            "Klass::layout_helper()",
+            REFERENCE_INSTANCE_SIZE as isize,
+            Some(klass),
+        ),
+        ("Klass::vtable_length()", VTABLE_LEN as isize, Some(klass)),
+        (
+            "InstanceKlass::itable_length()",
+            ITABLE_LEN as isize,
+            Some(klass),
+        ),
+        ("InstanceKlass::nonstatic_oop_map_count()", 2, Some(klass)),
+        (
+            "InstanceKlass::reference_type()",
+            ReferenceType::Weak as isize,
+            Some(klass),
+        ),
+        (
+            "InstanceKlass::start_of_nonstatic_oop_maps()",
+            oop_maps_offset as isize,
+            Some(klass),
+        ),
+    ]
+}
+
+fn matching_layout<const COMPRESSED: bool>() {
+    init();
+    let klass = InstanceKlassBuilder::reference(ReferenceType::Weak).build();
+    assert_eq!(
+        check::<COMPRESSED>(&mock_vm_entries::<COMPRESSED>(klass)),
+        Vec::<String>::new()
+    );
+}
+
+fn mismatches_are_reported_by_name<const COMPRESSED: bool>() {
+    init();
+    let klass = InstanceKlassBuilder::reference(ReferenceType::Weak).build();
+    let mut entries = mock_vm_entries::<COMPRESSED>(klass);
+    for entry in entries.iter_mut() {
+        if entry.0 == "arrayOopDesc::length_offset_in_bytes()"
+            || entry.0 == "InstanceKlass::itable_length()"
+        {
+            entry.1 += 4;
+        }
+    }
+    // The binding ignores entries that it does not know.
+    entries.push(("Klass::_unknown", 0, None));
+
+    let errors = check::<COMPRESSED>(&entries);
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert!(errors[0].starts_with("arrayOopDesc::length_offset_in_bytes(): "));
+    assert!(errors[1].starts_with("InstanceKlass::itable_length() of the klass at "));
+}
+
+test_with_compressed_oops!(matching_layout, mismatches_are_reported_by_name);
+
+#[test]
+fn nothing_reported() {
+    assert_eq!(check::<false>(&[]).len(), 1);
+}
@@ -0,0 +1,501 @@
+//! A mock VM for unit tests.
+//!
+//! It installs an `OpenJDK_Upcalls` table that answers the queries of object scanning and object
+//! sizing, and builds `Klass` and `OopDesc` memory with the layouts that `abi.rs` expects.  Klasses
+//! and objects are allocated from two arenas that are never freed, so that they are `'static`, and
+//! so that compressed klass pointers and compressed oops can be encoded relative to the start of
+//! the arenas.  Upcalls that a test is not expected to make abort the test.
+
+use crate::abi::*;
+use crate::object_model::object_alignment;
+use crate::slots;
+use crate::{
+    FieldClosure, KlassLayoutClosure, MutatorClosure, OpenJDKSlot, OpenJDK_Upcalls, SlotsClosure,
+    WeakRootsClosure,
+};
+use libc::{c_char, c_void};
+use mmtk::util::alloc::AllocationError;
+use mmtk::util::api_util::NullableObjectReference;
+use mmtk::util::constants::{BYTES_IN_PAGE, BYTES_IN_WORD, LOG_BYTES_IN_WORD};
+use mmtk::util::conversions::raw_align_up;
+use mmtk::util::opaque_pointer::*;
+use mmtk::util::{Address, ObjectReference};
+use std::alloc::Layout;
+use std::collections::HashMap;
+use std::mem;
+use std::ptr::addr_of_mut;
+use std::sync::atomic::Ordering;
+use std::sync::{Mutex, Once};
+
+/// The offset of the `Klass*` of the class represented by a `java.lang.Class` instance
+pub const MIRROR_KLASS_OFFSET: i32 = 16;
+/// The offset of the number of static oop fields in a `java.lang.Class` instance
+pub const MIRROR_STATIC_OOP_FIELD_COUNT_OFFSET: i32 = 24;
+/// The offset of the first static field in a `java.lang.Class` instance.  The instance fields of
+/// `java.lang.Class` end before it.
+pub const MIRROR_STATIC_FIELDS_OFFSET: i32 = 40;
+/// The offsets of the fields of `java.lang.ref.Reference`.  Each field gets a word, so the offsets
+/// are the same with compressed oops.
+pub const REFERENT_OFFSET: i32 = 16;
+pub const QUEUE_OFFSET: i32 = 24;
+pub const NEXT_OFFSET: i32 = 32;
+pub const DISCOVERED_OFFSET: i32 = 40;
+pub const REFERENCE_INSTANCE_SIZE: usize = 48;
+/// `java.lang.ref.SoftReference` adds a `long timestamp` after the fields of `Reference`.
+pub const SOFT_REFERENCE_TIMESTAMP_OFFSET: i32 = 48;
+pub const SOFT_REFERENCE_INSTANCE_SIZE: usize = 56;
+
+/// The mark word of an unlocked object without a hash code
+const MARK_UNLOCKED: usize = 1;
+/// The offset of the klass pointer in an object
+const KLASS_POINTER_OFFSET: usize = BYTES_IN_WORD;
+/// Each klass has a few vtable and itable entries, so that the oop maps do not start right after
+/// the `InstanceKlass` struct.
+pub const VTABLE_LEN: usize = 3;
+pub const ITABLE_LEN: usize = 2;
+/// Filled into the vtable and the itable.  Reading it as an oop map would yield bogus slots.
+const POISON: usize = 0xdead_beef_dead_beef;
+/// The array tags in the top two bits of the layout helper of array classes
+const LH_ARRAY_TAG_OBJ_VALUE: u32 = 0b10;
+const LH_ARRAY_TAG_TYPE_VALUE: u32 = 0b11;
+const LH_ARRAY_TAG_SHIFT: u32 = 30;
+
+/// A bump-pointer region of zeroed memory that is never freed
+struct Arena {
+    start: Address,
+    limit: Address,
+    cursor: Mutex<Address>,
+}
+
+impl Arena {
+    fn new(bytes: usize) -> Self {
+        let layout = Layout::from_size_align(bytes, BYTES_IN_PAGE).unwrap();
+        let start = Address::from_mut_ptr(unsafe { std::alloc::alloc_zeroed(layout) });
+        assert!(!start.is_zero(), "failed to allocate the mock arena");
+        // Leave the first word unused, so that no compressed pointer into the arena is 0.
+        Self {
+            start,
+            limit: start + bytes,
+            cursor: Mutex::new(start + BYTES_IN_WORD),
+        }
+    }
+
+    fn alloc(&self, bytes: usize) -> Address {
+        let mut cursor = self.cursor.lock().unwrap();
+        let result = *cursor;
+        *cursor = result + raw_align_up(bytes, BYTES_IN_WORD);
+        assert!(*cursor <= self.limit, "the mock arena is full");
+        result
+    }
+}
+
+lazy_static! {
+    static ref KLASS_ARENA: Arena = Arena::new(1 << 20);
+    static ref OBJECT_ARENA: Arena = Arena::new(16 << 20);
+    /// The sizes of objects whose klass requires the slow path of `OopDesc::size`
+    static ref SLOW_PATH_SIZES: Mutex<HashMap<ObjectReference, usize>> = Mutex::new(HashMap::new());
+}
+
+/// Install the mock upcalls, and set up the encodings of compressed klass pointers and compressed
+/// oops.  Every test should call this before using the other functions of this module.
+pub fn init() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        crate::upcalls::set_upcalls(&MOCK_UPCALLS);
+        set_compressed_klass_base_and_shift(KLASS_ARENA.start, LOG_BYTES_IN_WORD as usize);
+        slots::BASE.store(OBJECT_ARENA.start, Ordering::Relaxed);
+        slots::SHIFT.store(LOG_BYTES_IN_WORD as usize, Ordering::Relaxed);
+    });
+}
+
+/// The offset of the first element of an array.  The length follows the klass pointer, and the
+/// elements start at the next 8-byte boundary.
+pub fn array_header_in_bytes<const COMPRESSED: bool>() -> usize {
+    if COMPRESSED {
+        16
+    } else {
+        24
+    }
+}
+
+/// Builds an `InstanceKlass`, or one of its subclasses, depending on `id`.
+pub struct InstanceKlassBuilder {
+    id: KlassID,
+    instance_size: usize,
+    slow_path: bool,
+    oop_maps: Vec<(i32, u32)>,
+    reference_type: ReferenceType,
+}
+
+impl InstanceKlassBuilder {
+    pub fn new(instance_size: usize) -> Self {
+        Self {
+            id: KlassID::Instance,
+            instance_size,
+            slow_path: false,
+            oop_maps: vec![],
+            reference_type: ReferenceType::None,
+        }
+    }
+
+    /// A subclass of `java.lang.ref.Reference`, with the layout described by `REFERENT_OFFSET` and
+    /// the following constants.  As in HotSpot, `referent` and `discovered` are not in the oop maps.
+    pub fn reference(reference_type: ReferenceType) -> Self {
+        let instance_size = match reference_type {
+            ReferenceType::Soft => SOFT_REFERENCE_INSTANCE_SIZE,
+            _ => REFERENCE_INSTANCE_SIZE,
+        };
+        Self {
+            id: KlassID::InstanceRef,
+            reference_type,
+            ..Self::new(instance_size)
+        }
+        .oop_map(QUEUE_OFFSET, 1)
+        .oop_map(NEXT_OFFSET, 1)
+    }
+
+    /// The class of `java.lang.Class` instances.  Their sizes vary with the number of static
+    /// fields, so they are computed in the slow path.
+    pub fn mirror() -> Self {
+        Self {
+            id: KlassID::InstanceMirror,
+            slow_path: true,
+            ..Self::new(MIRROR_STATIC_FIELDS_OFFSET as usize)
+        }
+    }
+
+    pub fn id(mut self, id: KlassID) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Add an oop map block of `count` consecutive reference fields starting at `offset`.
+    pub fn oop_map(mut self, offset: i32, count: u32) -> Self {
+        self.oop_maps.push((offset, count));
+        self
+    }
+
+    /// Compute the size of instances with the `get_object_size` upcall.
+    pub fn slow_path(mut self) -> Self {
+        self.slow_path = true;
+        self
+    }
+
+    pub fn build(self) -> &'static Klass {
+        let oop_map_offset =
+            mem::size_of::<InstanceKlass>() + (VTABLE_LEN + ITABLE_LEN) * BYTES_IN_WORD;
+        let oop_map_size = mem::size_of::<OopMapBlock>();
+        let start = KLASS_ARENA.alloc(oop_map_offset + self.oop_maps.len() * oop_map_size);
+        let mut layout_helper = self.instance_size as i32;
+        if self.slow_path {
+            layout_helper |= Klass::LH_INSTANCE_SLOW_PATH_BIT;
+        }
+        let ik = start.to_mut_ptr::<InstanceKlass>();
+        unsafe {
+            addr_of_mut!((*ik).klass.layout_helper).write(layout_helper);
+            addr_of_mut!((*ik).klass.id).write(self.id);
+            addr_of_mut!((*ik).klass.vtable_len).write(VTABLE_LEN as i32);
+            addr_of_mut!((*ik).itable_len).write(ITABLE_LEN as i32);
+            addr_of_mut!((*ik).nonstatic_oop_map_size)
+                .write((self.oop_maps.len() * oop_map_size / BYTES_IN_WORD) as i32);
+            addr_of_mut!((*ik).reference_type).write(self.reference_type);
+            for i in 0..VTABLE_LEN + ITABLE_LEN {
+                (start + mem::size_of::<InstanceKlass>() + i * BYTES_IN_WORD).store(POISON);
+            }
+            for (i, &(offset, count)) in self.oop_maps.iter().enumerate() {
+                (start + oop_map_offset + i * oop_map_size).store(OopMapBlock { offset, count });
+            }
+            &*start.to_ptr::<Klass>()
+        }
+    }
+}
+
+fn build_array_klass<T>(id: KlassID, layout_helper: i32) -> &'static Klass {
+    let start = KLASS_ARENA.alloc(mem::size_of::<T>());
+    let klass = start.to_mut_ptr::<Klass>();
+    unsafe {
+        addr_of_mut!((*klass).layout_helper).write(layout_helper);
+        addr_of_mut!((*klass).id).write(id);
+        &*klass
+    }
+}
+
+fn array_layout_helper<const COMPRESSED: bool>(
+    tag: u32,
+    element_type: BasicType,
+    log2_element_size: u32,
+) -> i32 {
+    ((tag << LH_ARRAY_TAG_SHIFT)
+        | ((array_header_in_bytes::<COMPRESSED>() as u32) << Klass::LH_HEADER_SIZE_SHIFT)
+        | ((element_type as u32) << Klass::LH_ELEMENT_TYPE_SHIFT)
+        | (log2_element_size << Klass::LH_LOG2_ELEMENT_SIZE_SHIFT)) as i32
+}
+
+/// Build an `ObjArrayKlass`.  Its layout helper depends on whether oops are compressed.
+pub fn new_obj_array_klass<const COMPRESSED: bool>() -> &'static Klass {
+    let layout_helper = array_layout_helper::<COMPRESSED>(
+        LH_ARRAY_TAG_OBJ_VALUE,
+        BasicType::T_OBJECT,
+        OpenJDKSlot::<COMPRESSED>::LOG_BYTES_IN_SLOT as u32,
+    );
+    build_array_klass::<ObjArrayKlass>(KlassID::ObjArray, layout_helper)
+}
+
+/// Build a `TypeArrayKlass` for elements of `1 << log2_element_size` bytes.
+pub fn new_type_array_klass<const COMPRESSED: bool>(
+    element_type: BasicType,
+    log2_element_size: u32,
+) -> &'static Klass {
+    let layout_helper =
+        array_layout_helper::<COMPRESSED>(LH_ARRAY_TAG_TYPE_VALUE, element_type, log2_element_size);
+    build_array_klass::<TypeArrayKlass>(KlassID::TypeArray, layout_helper)
+}
+
+fn new_object<const COMPRESSED: bool>(klass: &'static Klass, bytes: usize) -> ObjectReference {
+    let start = OBJECT_ARENA.alloc(bytes);
+    let klass_addr = Address::from_ref(klass);
+    unsafe {
+        start.store(MARK_UNLOCKED);
+        if COMPRESSED {
+            let narrow_klass = (klass_addr - KLASS_ARENA.start) >> LOG_BYTES_IN_WORD;
+            (start + KLASS_POINTER_OFFSET).store(narrow_klass as u32);
+        } else {
+            (start + KLASS_POINTER_OFFSET).store(klass_addr);
+        }
+    }
+    ObjectReference::from_raw_address(start).unwrap()
+}
+
+/// Allocate an instance of `klass`, with the size in its layout helper.
+pub fn new_instance<const COMPRESSED: bool>(klass: &'static Klass) -> ObjectReference {
+    assert!(klass.layout_helper > 0 && klass.layout_helper & Klass::LH_INSTANCE_SLOW_PATH_BIT == 0);
+    new_object::<COMPRESSED>(klass, klass.layout_helper as usize)
+}
+
+/// Allocate an instance of `klass` of `bytes` bytes, which the `get_object_size` upcall reports.
+pub fn new_instance_with_size<const COMPRESSED: bool>(
+    klass: &'static Klass,
+    bytes: usize,
+) -> ObjectReference {
+    let object = new_object::<COMPRESSED>(klass, bytes);
+    SLOW_PATH_SIZES.lock().unwrap().insert(object, bytes);
+    object
+}
+
+/// Allocate a `java.lang.Class` instance of the mirror class `klass`, with
+/// `static_oop_field_count` static reference fields.
+pub fn new_mirror<const COMPRESSED: bool>(
+    klass: &'static Klass,
+    static_oop_field_count: usize,
+) -> ObjectReference {
+    let bytes = MIRROR_STATIC_FIELDS_OFFSET as usize
+        + (static_oop_field_count << OpenJDKSlot::<COMPRESSED>::LOG_BYTES_IN_SLOT);
+    let mirror =
+        new_instance_with_size::<COMPRESSED>(klass, raw_align_up(bytes, object_alignment()));
+    unsafe {
+        (mirror.to_raw_address() + MIRROR_STATIC_OOP_FIELD_COUNT_OFFSET as usize)
+            .store(static_oop_field_count as i32);
+    }
+    mirror
+}
+
+/// Allocate an array of `length` elements of the array class `klass`.
+pub fn new_array<const COMPRESSED: bool>(klass: &'static Klass, length: usize) -> ObjectReference {
+    let bytes = array_header_in_bytes::<COMPRESSED>() + (length << klass.array_log2_element_size());
+    let array = new_object::<COMPRESSED>(klass, raw_align_up(bytes, object_alignment()));
+    let length_offset = if COMPRESSED { 12 } else { 16 };
+    unsafe { (array.to_raw_address() + length_offset).store(length as i32) };
+    array
+}
+
+/// The slot of the reference field at `offset` of `object`
+pub fn field_slot<const COMPRESSED: bool>(
+    object: ObjectReference,
+    offset: i32,
+) -> OpenJDKSlot<COMPRESSED> {
+    (object.to_raw_address() + offset as isize).into()
+}
+
+/// The slot of the element `index` of an object array
+pub fn element_slot<const COMPRESSED: bool>(
+    array: ObjectReference,
+    index: usize,
+) -> OpenJDKSlot<COMPRESSED> {
+    (array.to_raw_address()
+        + array_header_in_bytes::<COMPRESSED>()
+        + (index << OpenJDKSlot::<COMPRESSED>::LOG_BYTES_IN_SLOT))
+        .into()
+}
+
+/// The slot of the static reference field `index` of a `java.lang.Class` instance
+pub fn static_field_slot<const COMPRESSED: bool>(
+    mirror: ObjectReference,
+    index: usize,
+) -> OpenJDKSlot<COMPRESSED> {
+    field_slot(
+        mirror,
+        MIRROR_STATIC_FIELDS_OFFSET
+            + (index << OpenJDKSlot::<COMPRESSED>::LOG_BYTES_IN_SLOT) as i32,
+    )
+}
+
+/// Scan `object`, and return the slots in the order they are visited.
+pub fn scan<const COMPRESSED: bool>(object: ObjectReference) -> Vec<OpenJDKSlot<COMPRESSED>> {
+    let mut slots = vec![];
+    crate::object_scanning::scan_object::<COMPRESSED>(
+        object,
+        &mut |slot: OpenJDKSlot<COMPRESSED>| slots.push(slot),
+        VMWorkerThread(VMThread::UNINITIALIZED),
+    );
+    slots
+}
+
+extern "C" fn get_object_size(object: ObjectReference) -> usize {
+    match SLOW_PATH_SIZES.lock().unwrap().get(&object) {
+        Some(&bytes) => bytes,
+        None => unexpected("get_object_size"),
+    }
+}
+
+extern "C" fn dump_object_string(_object: ObjectReference) -> *const c_char {
+    b"mock object\0".as_ptr() as *const c_char
+}
+
+extern "C" fn offset_of_static_fields() -> i32 {
+    MIRROR_STATIC_FIELDS_OFFSET
+}
+
+extern "C" fn static_oop_field_count_offset() -> i32 {
+    MIRROR_STATIC_OOP_FIELD_COUNT_OFFSET
+}
+
+extern "C" fn referent_offset() -> i32 {
+    REFERENT_OFFSET
+}
+
+extern "C" fn discovered_offset() -> i32 {
+    DISCOVERED_OFFSET
+}
+
+extern "C" fn next_offset() -> i32 {
+    NEXT_OFFSET
+}
+
+extern "C" fn soft_reference_timestamp_offset() -> i32 {
+    SOFT_REFERENCE_TIMESTAMP_OFFSET
+}
+
+extern "C" fn mirror_klass_offset() -> i32 {
+    MIRROR_KLASS_OFFSET
+}
+
+/// Upcalls must not unwind, so we abort instead of panicking.
+fn unexpected(name: &str) -> ! {
+    eprintln!("Unexpected upcall in the mock VM: {}", name);
+    std::process::abort()
+}
+
+macro_rules! unexpected_upcalls {
+    ($($name:ident($($arg:ty),*) $(-> $ret:ty)?;)*) => {
+        $(
+            extern "C" fn $name($(_: $arg),*) $(-> $ret)? {
+                unexpected(stringify!($name))
+            }
+        )*
+    };
+}
+
+unexpected_upcalls! {
+    stop_all_mutators(VMWorkerThread, MutatorClosure);
+    resume_mutators(VMWorkerThread);
+    spawn_gc_thread(VMThread, libc::c_int, *mut c_void);
+    block_for_gc();
+    out_of_memory(VMThread, AllocationError);
+    get_mutators(MutatorClosure);
+    scan_object(*mut c_void, ObjectReference, OpaquePointer);
+    dump_object(ObjectReference);
+    get_mmtk_mutator(VMMutatorThread) -> *mut c_void;
+    is_mutator(VMThread) -> bool;
+    harness_begin();
+    harness_end();
+    report_klass_mem_layout(KlassLayoutClosure);
+    scan_roots_in_all_mutator_threads(SlotsClosure);
+    scan_roots_in_mutator_thread(SlotsClosure, VMMutatorThread);
+    scan_universe_roots(SlotsClosure);
+    scan_jni_handle_roots(SlotsClosure);
+    scan_object_synchronizer_roots(SlotsClosure);
+    scan_management_roots(SlotsClosure);
+    scan_jvmti_export_roots(SlotsClosure);
+    scan_aot_loader_roots(SlotsClosure);
+    scan_system_dictionary_roots(SlotsClosure);
+    scan_code_cache_roots(SlotsClosure);
+    scan_string_table_roots(SlotsClosure);
+    process_string_table(WeakRootsClosure);
+    scan_class_loader_data_graph_roots(SlotsClosure);
+    scan_weak_processor_roots(SlotsClosure);
+    process_weak_processor_roots(WeakRootsClosure);
+    scan_vm_thread_roots(SlotsClosure);
+    number_of_mutators() -> usize;
+    schedule_finalizer();
+    prepare_for_roots_re_scanning();
+    enqueue_references(*const ObjectReference, usize);
+    klass_name(*const Klass, *mut c_char, usize) -> usize;
+    klass_class_loader(*const Klass) -> NullableObjectReference;
+    iterate_klass_fields(*const Klass, FieldClosure);
+    sample_allocation(VMMutatorThread, ObjectReference, usize);
+    soft_reference_clock_offset() -> i32;
+    soft_reference_mirror() -> ObjectReference;
+}
+
+pub static MOCK_UPCALLS: OpenJDK_Upcalls = OpenJDK_Upcalls {
+    version: crate::upcalls::UPCALLS_VERSION,
+    size: mem::size_of::<OpenJDK_Upcalls>(),
+    stop_all_mutators,
+    resume_mutators,
+    spawn_gc_thread,
+    block_for_gc,
+    out_of_memory,
+    get_mutators,
+    scan_object,
+    dump_object,
+    get_object_size,
+    get_mmtk_mutator,
+    is_mutator,
+    harness_begin,
+    harness_end,
+    report_klass_mem_layout,
+    offset_of_static_fields,
+    static_oop_field_count_offset,
+    referent_offset,
+    discovered_offset,
+    next_offset,
+    soft_reference_timestamp_offset,
+    soft_reference_clock_offset,
+    soft_reference_mirror,
+    dump_object_string,
+    scan_roots_in_all_mutator_threads,
+    scan_roots_in_mutator_thread,
+    scan_universe_roots,
+    scan_jni_handle_roots,
+    scan_object_synchronizer_roots,
+    scan_management_roots,
+    scan_jvmti_export_roots,
+    scan_aot_loader_roots,
+    scan_system_dictionary_roots,
+    scan_code_cache_roots,
+    scan_string_table_roots,
+    process_string_table,
+    scan_class_loader_data_graph_roots,
+    scan_weak_processor_roots,
+    process_weak_processor_roots,
+    scan_vm_thread_roots,
+    number_of_mutators,
+    schedule_finalizer,
+    prepare_for_roots_re_scanning,
+    enqueue_references,
+    mirror_klass_offset,
+    klass_name,
+    klass_class_loader,
+    iterate_klass_fields,
+    sample_allocation,
+};
@@ -0,0 +1,34 @@
+//! Unit tests that run with `cargo test`, without a JVM.  `mock_vm` provides the upcalls and the
+//! klass and object layouts that the tests need.
+
+/// Run the generic test function `$name::<COMPRESSED>` with and without compressed oops, as the
+/// tests `$name::uncompressed` and `$name::compressed`.  Compressed oops are only supported on
+/// 64-bit platforms.
+macro_rules! test_with_compressed_oops {
+    ($($name:ident),* $(,)?) => {
+        $(
+            mod $name {
+                #[test]
+                fn uncompressed() {
+                    super::$name::<false>()
+                }
+
+                #[cfg(target_pointer_width = "64")]
+                #[test]
+                fn compressed() {
+                    super::$name::<true>()
+                }
+            }
+        )*
+    };
+}
+
+mod abi;
+mod mock_vm;
+mod object_scanning;
+mod object_size;
+mod slots;
+mod soft_references;
+mod upcalls;
+#[cfg(target_pointer_width = "64")]
+mod vm_layout;
@@ -0,0 +1,220 @@
+use super::mock_vm::*;
+use crate::abi::{BasicType, InstanceKlass, KlassID, OopMapBlock, ReferenceType};
+use crate::object_scanning::invalidate_scan_plans;
+use crate::verify::with_references_as_strong;
+use mmtk::vm::slot::Slot;
+
+const REFERENCE_TYPES: [ReferenceType; 5] = [
+    ReferenceType::Other,
+    ReferenceType::Soft,
+    ReferenceType::Weak,
+    ReferenceType::Final,
+    ReferenceType::Phantom,
+];
+
+fn scan_instance<const COMPRESSED: bool>() {
+    init();
+    let klass = InstanceKlassBuilder::new(48)
+        .oop_map(16, 2)
+        .oop_map(40, 1)
+        .build();
+    let object = new_instance::<COMPRESSED>(klass);
+    let slot_bytes = crate::OpenJDKSlot::<COMPRESSED>::BYTES_IN_SLOT as i32;
+    assert_eq!(
+        scan::<COMPRESSED>(object),
+        vec![
+            field_slot(object, 16),
+            field_slot(object, 16 + slot_bytes),
+            field_slot(object, 40),
+        ]
+    );
+}
+
+fn scan_instance_without_oop_maps<const COMPRESSED: bool>() {
+    init();
+    let klass = InstanceKlassBuilder::new(24).build();
+    let object = new_instance::<COMPRESSED>(klass);
+    assert!(scan::<COMPRESSED>(object).is_empty());
+}
+
+fn scan_class_loader<const COMPRESSED: bool>() {
+    init();
+    let klass = InstanceKlassBuilder::new(32)
+        .id(KlassID::InstanceClassLoader)
+        .oop_map(24, 1)
+        .build();
+    let object = new_instance::<COMPRESSED>(klass);
+    assert_eq!(scan::<COMPRESSED>(object), vec![field_slot(object, 24)]);
+}
+
+fn scan_mirror<const COMPRESSED: bool>() {
+    init();
+    let klass = InstanceKlassBuilder::mirror().oop_map(32, 1).build();
+    let mirror = new_mirror::<COMPRESSED>(klass, 3);
+    assert_eq!(
+        scan::<COMPRESSED>(mirror),
+        vec![
+            field_slot(mirror, 32),
+            static_field_slot(mirror, 0),
+            static_field_slot(mirror, 1),
+            static_field_slot(mirror, 2),
+        ]
+    );
+}
+
+fn scan_obj_array<const COMPRESSED: bool>() {
+    init();
+    let array_klass = new_obj_array_klass::<COMPRESSED>();
+    let element_klass = InstanceKlassBuilder::new(16).build();
+    let array = new_array::<COMPRESSED>(array_klass, 4);
+    let elements: Vec<_> = (0..3)
+        .map(|_| new_instance::<COMPRESSED>(element_klass))
+        .collect();
+    for (i, element) in elements.iter().enumerate() {
+        element_slot::<COMPRESSED>(array, i).store(*element);
+    }
+
+    let slots = scan::<COMPRESSED>(array);
+    assert_eq!(
+        slots,
+        (0..4)
+            .map(|i| element_slot::<COMPRESSED>(array, i))
+            .collect::<Vec<_>>()
+    );
+    let loaded: Vec<_> = slots.iter().map(|slot| slot.load()).collect();
+    assert_eq!(
+        loaded,
+        vec![
+            Some(elements[0]),
+            Some(elements[1]),
+            Some(elements[2]),
+            None
+        ]
+    );
+}
+
+fn scan_empty_obj_array<const COMPRESSED: bool>() {
+    init();
+    let array = new_array::<COMPRESSED>(new_obj_array_klass::<COMPRESSED>(), 0);
+    assert!(scan::<COMPRESSED>(array).is_empty());
+}
+
+fn scan_type_array<const COMPRESSED: bool>() {
+    init();
+    let klass = new_type_array_klass::<COMPRESSED>(BasicType::T_INT, 2);
+    let array = new_array::<COMPRESSED>(klass, 10);
+    assert!(scan::<COMPRESSED>(array).is_empty());
+}
+
+fn scan_reference_as_strong<const COMPRESSED: bool>() {
+    init();
+    for reference_type in REFERENCE_TYPES {
+        let klass = InstanceKlassBuilder::reference(reference_type).build();
+        let reference = new_instance::<COMPRESSED>(klass);
+        assert_eq!(
+            with_references_as_strong(|| scan::<COMPRESSED>(reference)),
+            vec![
+                field_slot(reference, QUEUE_OFFSET),
+                field_slot(reference, NEXT_OFFSET),
+                field_slot(reference, DISCOVERED_OFFSET),
+                field_slot(reference, REFERENT_OFFSET),
+            ],
+            "{:?}",
+            reference_type
+        );
+    }
+}
+
+/// The scan plan of a klass is cached, and rebuilt after it is invalidated.
+fn scan_plan_is_rebuilt_after_invalidation<const COMPRESSED: bool>() {
+    init();
+    let klass = InstanceKlassBuilder::new(32).oop_map(16, 2).build();
+    let object = new_instance::<COMPRESSED>(klass);
+    assert_eq!(scan::<COMPRESSED>(object).len(), 2);
+
+    // Pretend that the class was redefined with a different layout at the same address.
+    let oop_maps = unsafe { klass.cast::<InstanceKlass>() }.nonstatic_oop_maps();
+    unsafe { (*(oop_maps.as_ptr() as *mut OopMapBlock)).count = 1 };
+    invalidate_scan_plans();
+    assert_eq!(scan::<COMPRESSED>(object), vec![field_slot(object, 16)]);
+}
+
+test_with_compressed_oops!(
+    scan_instance,
+    scan_instance_without_oop_maps,
+    scan_class_loader,
+    scan_mirror,
+    scan_obj_array,
+    scan_empty_obj_array,
+    scan_type_array,
+    scan_reference_as_strong,
+    scan_plan_is_rebuilt_after_invalidation,
+);
+
+/// Discovering references registers them with MMTk, so this test creates an MMTk instance.  We
+/// only do it without compressed oops, because the choice is global.
+#[test]
+fn scan_reference_discovers_referent() {
+    init();
+    for reference_type in REFERENCE_TYPES {
+        let klass = InstanceKlassBuilder::reference(reference_type).build();
+        let reference = new_instance::<false>(klass);
+        let mut expected = vec![
+            field_slot(reference, QUEUE_OFFSET),
+            field_slot(reference, NEXT_OFFSET),
+            field_slot(reference, DISCOVERED_OFFSET),
+        ];
+        // References without a referent are not discovered.  Other references are processed as
+        // strong references.
+        if matches!(reference_type, ReferenceType::Other | ReferenceType::Final) {
+            expected.push(field_slot(reference, REFERENT_OFFSET));
+        }
+        assert_eq!(scan::<false>(reference), expected, "{:?}", reference_type);
+    }
+}
+
+/// An active `FinalReference` is registered with MMTk once, and its referent is not traced.
+#[test]
+fn scan_final_reference_registers_it_once() {
+    init();
+    let klass = InstanceKlassBuilder::reference(ReferenceType::Final).build();
+    let reference = new_instance::<false>(klass);
+    let referent = new_instance::<false>(InstanceKlassBuilder::new(16).build());
+    field_slot::<false>(reference, REFERENT_OFFSET).store(referent);
+    let expected = vec![
+        field_slot(reference, QUEUE_OFFSET),
+        field_slot(reference, NEXT_OFFSET),
+        field_slot(reference, DISCOVERED_OFFSET),
+    ];
+    for _ in 0..2 {
+        assert_eq!(scan::<false>(reference), expected);
+        assert_eq!(
+            field_slot::<false>(reference, DISCOVERED_OFFSET).load(),
+            Some(reference)
+        );
+    }
+}
+
+/// A `FinalReference` in the pending list is inactive, and its referent is strongly reachable.
+#[test]
+fn scan_inactive_final_reference_as_strong() {
+    init();
+    let klass = InstanceKlassBuilder::reference(ReferenceType::Final).build();
+    let reference = new_instance::<false>(klass);
+    let referent = new_instance::<false>(InstanceKlassBuilder::new(16).build());
+    field_slot::<false>(reference, REFERENT_OFFSET).store(referent);
+    field_slot::<false>(reference, NEXT_OFFSET).store(reference);
+    assert_eq!(
+        scan::<false>(reference),
+        vec![
+            field_slot(reference, QUEUE_OFFSET),
+            field_slot(reference, NEXT_OFFSET),
+            field_slot(reference, DISCOVERED_OFFSET),
+            field_slot(reference, REFERENT_OFFSET),
+        ]
+    );
+    assert_eq!(
+        field_slot::<false>(reference, DISCOVERED_OFFSET).load(),
+        None
+    );
+}
@@ -0,0 +1,87 @@
+use super::mock_vm::*;
+use crate::abi::{BasicType, Oop};
+use crate::object_model::object_alignment;
+use mmtk::util::conversions::raw_align_up;
+
+fn size_of<const COMPRESSED: bool>(object: mmtk::util::ObjectReference) -> usize {
+    unsafe { Oop::from(object).size::<COMPRESSED>() }
+}
+
+fn instance_size<const COMPRESSED: bool>() {
+    init();
+    for bytes in [16, 24, 48, 1024] {
+        let klass = InstanceKlassBuilder::new(bytes).build();
+        assert_eq!(
+            size_of::<COMPRESSED>(new_instance::<COMPRESSED>(klass)),
+            bytes
+        );
+    }
+}
+
+fn instance_size_slow_path<const COMPRESSED: bool>() {
+    init();
+    let klass = InstanceKlassBuilder::new(24).slow_path().build();
+    let object = new_instance_with_size::<COMPRESSED>(klass, 56);
+    assert_eq!(size_of::<COMPRESSED>(object), 56);
+
+    let mirror_klass = InstanceKlassBuilder::mirror().build();
+    let mirror = new_mirror::<COMPRESSED>(mirror_klass, 5);
+    let static_fields = 5 * crate::OpenJDKSlot::<COMPRESSED>::BYTES_IN_SLOT;
+    assert_eq!(
+        size_of::<COMPRESSED>(mirror),
+        raw_align_up(
+            MIRROR_STATIC_FIELDS_OFFSET as usize + static_fields,
+            object_alignment()
+        )
+    );
+}
+
+fn type_array_size<const COMPRESSED: bool>() {
+    init();
+    let header = array_header_in_bytes::<COMPRESSED>();
+    let cases = [
+        (BasicType::T_BYTE, 0),
+        (BasicType::T_CHAR, 1),
+        (BasicType::T_INT, 2),
+        (BasicType::T_LONG, 3),
+    ];
+    for (element_type, log2_element_size) in cases {
+        let klass = new_type_array_klass::<COMPRESSED>(element_type, log2_element_size);
+        for length in [0, 1, 3, 9] {
+            let array = new_array::<COMPRESSED>(klass, length);
+            let expected = raw_align_up(header + (length << log2_element_size), object_alignment());
+            assert_eq!(
+                size_of::<COMPRESSED>(array),
+                expected,
+                "{:?}[{}]",
+                element_type,
+                length
+            );
+        }
+    }
+}
+
+fn obj_array_size<const COMPRESSED: bool>() {
+    init();
+    let klass = new_obj_array_klass::<COMPRESSED>();
+    let header = array_header_in_bytes::<COMPRESSED>();
+    let slot_bytes = crate::OpenJDKSlot::<COMPRESSED>::BYTES_IN_SLOT;
+    for length in [0, 1, 3, 9] {
+        let array = new_array::<COMPRESSED>(klass, length);
+        assert_eq!(
+            unsafe { Oop::from(array).as_array_oop() }.length::<COMPRESSED>(),
+            length as i32
+        );
+        assert_eq!(
+            size_of::<COMPRESSED>(array),
+            raw_align_up(header + length * slot_bytes, object_alignment())
+        );
+    }
+}
+
+test_with_compressed_oops!(
+    instance_size,
+    instance_size_slow_path,
+    type_array_size,
+    obj_array_size,
+);
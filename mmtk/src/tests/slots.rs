@@ -0,0 +1,62 @@
+use super::mock_vm::*;
+use crate::OpenJDKSlot;
+use mmtk::util::Address;
+use mmtk::vm::slot::Slot;
+
+fn store_and_load<const COMPRESSED: bool>() {
+    init();
+    let klass = InstanceKlassBuilder::new(32).oop_map(16, 2).build();
+    let holder = new_instance::<COMPRESSED>(klass);
+    let target = new_instance::<COMPRESSED>(klass);
+    let slot = field_slot::<COMPRESSED>(holder, 16);
+    let next_slot = slot.addr + OpenJDKSlot::<COMPRESSED>::BYTES_IN_SLOT;
+    assert_eq!(slot.load(), None);
+
+    slot.store(target);
+    assert_eq!(slot.load(), Some(target));
+    // The store must not overwrite the next field.
+    assert_eq!(OpenJDKSlot::<COMPRESSED>::from(next_slot).load(), None);
+
+    slot.store_null();
+    assert_eq!(slot.load(), None);
+}
+
+test_with_compressed_oops!(store_and_load);
+
+/// With compressed oops, root slots are full words, and are tagged by setting bit 63.
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn tagged_root_slot() {
+    init();
+    let klass = InstanceKlassBuilder::new(16).build();
+    let target = new_instance::<true>(klass);
+    let mut root = Address::ZERO;
+    let slot = OpenJDKSlot::<true>::from(unsafe {
+        Address::from_usize(Address::from_mut_ptr(&mut root).as_usize() | (1usize << 63))
+    });
+
+    slot.store(target);
+    assert_eq!(root, target.to_raw_address());
+    assert_eq!(slot.load(), Some(target));
+    slot.store_null();
+    assert_eq!(root, Address::ZERO);
+}
+
+/// On x86, oops embedded in compiled code are immediates in the instructions, and may be
+/// unaligned.
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn unaligned_embedded_slot() {
+    init();
+    let klass = InstanceKlassBuilder::new(16).build();
+    let target = new_instance::<false>(klass);
+    let mut code = [0u8; 16];
+    let slot = OpenJDKSlot::<false>::from(Address::from_mut_ptr(&mut code) + 3usize);
+
+    slot.store(target);
+    assert_eq!(
+        &code[3..11],
+        &target.to_raw_address().as_usize().to_ne_bytes()
+    );
+    assert_eq!(slot.load(), Some(target));
+}
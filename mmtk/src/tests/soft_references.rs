@@ -0,0 +1,29 @@
+use crate::soft_references::{lru_should_clear, max_interval};
+
+const MB: usize = 1 << 20;
+
+#[test]
+fn max_interval_grows_with_free_heap() {
+    assert_eq!(max_interval(0, 1000), 0);
+    assert_eq!(max_interval(MB - 1, 1000), 0);
+    assert_eq!(max_interval(64 * MB, 1000), 64_000);
+    assert_eq!(max_interval(64 * MB, 0), 0);
+    assert_eq!(max_interval(usize::MAX, usize::MAX), i64::MAX);
+}
+
+#[test]
+fn recently_used_soft_references_are_kept() {
+    // Used 5 seconds ago, and may stay unused for 10 seconds.
+    assert!(!lru_should_clear(15_000, 10_000, 10_000));
+    // Used exactly as long ago as allowed.
+    assert!(!lru_should_clear(20_000, 10_000, 10_000));
+    // Used in the current clock period.
+    assert!(!lru_should_clear(10_000, 10_000, 0));
+}
+
+#[test]
+fn stale_soft_references_are_cleared() {
+    assert!(lru_should_clear(20_001, 10_000, 10_000));
+    assert!(lru_should_clear(10_001, 10_000, 0));
+    assert!(lru_should_clear(i64::MAX, i64::MIN, i64::MAX - 1));
+}
@@ -0,0 +1,56 @@
+use super::mock_vm::MOCK_UPCALLS;
+use crate::upcalls::{check_upcalls, UPCALLS_VERSION};
+use crate::OpenJDK_Upcalls;
+use std::mem;
+
+const WORDS: usize = mem::size_of::<OpenJDK_Upcalls>() / mem::size_of::<usize>();
+
+/// Copy the mock table as words, so that we can corrupt it.
+fn mock_table() -> [usize; WORDS] {
+    unsafe { mem::transmute_copy(&MOCK_UPCALLS) }
+}
+
+fn check(table: &[usize; WORDS]) -> Result<(), String> {
+    check_upcalls(table.as_ptr() as *const OpenJDK_Upcalls)
+}
+
+#[test]
+fn valid_table() {
+    assert_eq!(check(&mock_table()), Ok(()));
+}
+
+#[test]
+fn null_table() {
+    assert!(check_upcalls(std::ptr::null()).is_err());
+}
+
+#[test]
+fn wrong_version() {
+    let mut table = mock_table();
+    table[0] = UPCALLS_VERSION + 1;
+    let error = check(&table).unwrap_err();
+    assert!(error.contains("version"), "{}", error);
+}
+
+#[test]
+fn wrong_size() {
+    let mut table = mock_table();
+    table[1] -= mem::size_of::<usize>();
+    let error = check(&table).unwrap_err();
+    assert!(error.contains("bytes"), "{}", error);
+}
+
+#[test]
+fn missing_entries_are_named() {
+    let mut table = mock_table();
+    // The entries after the version and the size are `stop_all_mutators`, `resume_mutators`, ...
+    table[3] = 0;
+    table[WORDS - 1] = 0;
+    let error = check(&table).unwrap_err();
+    assert!(
+        error.starts_with("Missing upcalls: resume_mutators, "),
+        "{}",
+        error
+    );
+    assert_eq!(error.matches(", ").count(), 1, "{}", error);
+}
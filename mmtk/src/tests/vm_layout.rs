@@ -0,0 +1,77 @@
+//! The heap range and the encoding of compressed oops with 16-byte and 32-byte object alignment
+
+use crate::compressed_pointer_vm_layout;
+use crate::slots::{compressed_oops_base_and_shift, HEAP_BASE_GAP};
+use mmtk::util::Address;
+
+const GB: usize = 1 << 30;
+
+/// Compute the layout for `max_heap_size` and check that the heap can hold it, and that the first
+/// and the last object in the heap are encoded as non-null narrow oops and decoded back.  Returns
+/// the end of the heap, and the base and the shift of compressed oops.
+fn layout_and_encoding(max_heap_size: usize, log_alignment: usize) -> (Address, Address, usize) {
+    let layout = compressed_pointer_vm_layout(max_heap_size, log_alignment);
+    assert!(layout.heap_end - layout.heap_start >= max_heap_size);
+    let (base, shift) =
+        compressed_oops_base_and_shift(layout.heap_start, layout.heap_end, log_alignment);
+    for addr in [layout.heap_start, layout.heap_end - (1 << log_alignment)] {
+        let narrow_oop = (addr - base) >> shift;
+        assert!(
+            narrow_oop != 0 && narrow_oop <= u32::MAX as usize,
+            "{:?}",
+            addr
+        );
+        assert_eq!(base + (narrow_oop << shift), addr);
+    }
+    (layout.heap_end, base, shift)
+}
+
+#[test]
+fn unshifted() {
+    for log_alignment in [4, 5] {
+        let (heap_end, base, shift) = layout_and_encoding(2 * GB, log_alignment);
+        assert_eq!(heap_end.as_usize(), 4 * GB);
+        assert_eq!((base, shift), (Address::ZERO, 0));
+    }
+}
+
+#[test]
+fn zero_based_16_byte_alignment() {
+    let (heap_end, base, shift) = layout_and_encoding(32 * GB, 4);
+    assert_eq!(heap_end.as_usize(), 64 * GB);
+    assert_eq!((base, shift), (Address::ZERO, 4));
+}
+
+#[test]
+fn zero_based_32_byte_alignment() {
+    let (heap_end, base, shift) = layout_and_encoding(100 * GB, 5);
+    assert_eq!(heap_end.as_usize(), 128 * GB);
+    assert_eq!((base, shift), (Address::ZERO, 5));
+}
+
+/// If the heap does not fit below the largest zero-based narrow oop, the base is just below the
+/// heap, and the heap ends below the largest narrow oop.
+#[test]
+fn heap_based() {
+    for (log_alignment, max_encoded_size) in [(4, 64 * GB), (5, 128 * GB)] {
+        let max_heap_size = max_encoded_size - GB / 2;
+        let (heap_end, base, shift) = layout_and_encoding(max_heap_size, log_alignment);
+        let heap_start = crate::COMPRESSED_HEAP_START;
+        assert_eq!(base.as_usize(), heap_start - HEAP_BASE_GAP);
+        assert_eq!(shift, log_alignment);
+        assert!(heap_end.as_usize() <= base.as_usize() + max_encoded_size);
+        assert!(heap_end.as_usize() > heap_start + max_heap_size);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Heap size is larger than 64 GB")]
+fn heap_too_large_for_16_byte_alignment() {
+    compressed_pointer_vm_layout(65 * GB, 4);
+}
+
+#[test]
+#[should_panic(expected = "Heap size is larger than 128 GB")]
+fn heap_too_large_for_32_byte_alignment() {
+    compressed_pointer_vm_layout(129 * GB, 5);
+}
@@ -0,0 +1,146 @@
+//! Report the bump-pointer buffer of the default allocator of each mutator as its TLAB, so that
+//! HotSpot's `tlab_capacity` and `tlab_used` reflect MMTk allocation.  Only these numbers are
+//! reported.  HotSpot's own TLAB stays empty, and HotSpot never allocates in the MMTk buffer.
+//!
+//! An allocator only knows the cursor and the limit of its current buffer.  To compute the
+//! capacity and the used bytes of the buffer, we also need its start.  MMTk refills the buffer
+//! only in the allocation slow path, which always goes through `alloc` below.  When `alloc` sees
+//! that the limit changed, it records the cursor after the allocation as the start of the buffer,
+//! next to the mutator.  So the reported buffer does not include the object whose allocation
+//! caused the refill.  We never infer where that object went.
+
+use crate::OpenJDK;
+use mmtk::util::alloc::allocators::AllocatorInfo;
+use mmtk::util::alloc::BumpPointer;
+use mmtk::util::Address;
+use mmtk::{memory_manager, AllocationSemantics, Mutator};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The buffer of a mutator, in the same terms as HotSpot's `ThreadLocalAllocBuffer`.  All fields
+/// are zero if the mutator has no buffer, or if the default allocator is not a bump-pointer
+/// allocator.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TLAB {
+    pub start: Address,
+    pub top: Address,
+    pub end: Address,
+}
+
+impl TLAB {
+    const EMPTY: TLAB = TLAB {
+        start: Address::ZERO,
+        top: Address::ZERO,
+        end: Address::ZERO,
+    };
+}
+
+/// The start of the current buffer of a mutator, and the limit of the buffer when the start was
+/// recorded.  The start is stale once the limit changes.  The VM keeps this right after each
+/// mutator, at offset `MUTATOR_SIZE` (`tlab_start` and `tlab_limit` in `MMTkMutatorContext`).
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct BufferStart {
+    start: Address,
+    limit: Address,
+}
+
+fn buffer_start_address<const COMPRESSED: bool>(mutator: &Mutator<OpenJDK<COMPRESSED>>) -> Address {
+    Address::from_ref(mutator) + crate::MUTATOR_SIZE
+}
+
+const NO_BUMP_POINTER: usize = usize::MAX;
+
+/// The offset of the bump pointer of the default allocator in a mutator, or `NO_BUMP_POINTER` if
+/// the default allocator does not allocate with a bump pointer
+static BUMP_POINTER_OFFSET: AtomicUsize = AtomicUsize::new(NO_BUMP_POINTER);
+
+/// Called after MMTk is initialized.  Find the bump pointer of the default allocator.
+pub(crate) fn init<const COMPRESSED: bool>() {
+    let selector = memory_manager::get_allocator_mapping(
+        crate::singleton::<COMPRESSED>(),
+        AllocationSemantics::Default,
+    );
+    if let AllocatorInfo::BumpPointer {
+        bump_pointer_offset,
+    } = AllocatorInfo::new::<OpenJDK<COMPRESSED>>(selector)
+    {
+        BUMP_POINTER_OFFSET.store(bump_pointer_offset, Ordering::Relaxed);
+    }
+}
+
+/// Return the cursor and the limit of the default allocator of `mutator`, or `None` if the
+/// default allocator does not allocate with a bump pointer.
+fn default_buffer<const COMPRESSED: bool>(
+    mutator: &Mutator<OpenJDK<COMPRESSED>>,
+) -> Option<(Address, Address)> {
+    let offset = BUMP_POINTER_OFFSET.load(Ordering::Relaxed);
+    if offset == NO_BUMP_POINTER {
+        return None;
+    }
+    let bump_pointer = unsafe { &*(Address::from_ref(mutator) + offset).to_ptr::<BumpPointer>() };
+    Some((bump_pointer.cursor, bump_pointer.limit))
+}
+
+/// Allocate with `memory_manager::alloc`, and record the start of the buffer if the default
+/// allocator acquired a new one.  Also count the bytes allocated with the default allocator for
+/// allocation sampling: objects in a buffer are counted when the buffer is retired, and other
+/// objects right away.
+pub(crate) fn alloc<const COMPRESSED: bool>(
+    mutator: &mut Mutator<OpenJDK<COMPRESSED>>,
+    size: usize,
+    align: usize,
+    offset: usize,
+    semantics: AllocationSemantics,
+) -> Address {
+    if !matches!(semantics, AllocationSemantics::Default) {
+        return memory_manager::alloc(mutator, size, align, offset, semantics);
+    }
+
+    let Some((cursor_before, limit_before)) = default_buffer(mutator) else {
+        return memory_manager::alloc(mutator, size, align, offset, semantics);
+    };
+    let result = memory_manager::alloc(mutator, size, align, offset, semantics);
+    let (cursor, limit) = default_buffer(mutator).unwrap();
+    if limit != limit_before {
+        // The buffer was refilled.  Everything below the cursor is attributed to this call.
+        let slot = buffer_start_address(mutator);
+        let retired = unsafe { slot.load::<BufferStart>() };
+        unsafe {
+            slot.store(BufferStart {
+                start: cursor,
+                limit,
+            })
+        };
+        if retired.limit == limit_before && retired.start <= cursor_before {
+            crate::alloc_sampling::count_allocated_bytes(cursor_before - retired.start);
+        }
+        crate::alloc_sampling::count_allocated_bytes(size);
+    } else if !(cursor_before <= result && result < cursor) {
+        // The object is not in the buffer, e.g. a medium object that Immix allocates elsewhere.
+        crate::alloc_sampling::count_allocated_bytes(size);
+    }
+    result
+}
+
+/// Return the current buffer of `mutator`.  If we have not seen the start of the buffer, we
+/// report it as empty.
+pub(crate) fn get_tlab<const COMPRESSED: bool>(mutator: &Mutator<OpenJDK<COMPRESSED>>) -> TLAB {
+    let Some((cursor, limit)) = default_buffer(mutator) else {
+        return TLAB::EMPTY;
+    };
+    if limit.is_zero() {
+        return TLAB::EMPTY;
+    }
+    let recorded = unsafe { buffer_start_address(mutator).load::<BufferStart>() };
+    let start = if recorded.limit == limit && recorded.start <= cursor {
+        recorded.start
+    } else {
+        cursor
+    };
+    TLAB {
+        start,
+        top: cursor,
+        end: limit,
+    }
+}
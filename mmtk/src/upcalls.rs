@@ -0,0 +1,152 @@
+//! The upcalls of the VM.
+//!
+//! The C++ side passes a table of function pointers to `openjdk_gc_init`.  The table starts with a
+//! version and its size, so that a VM built against a different layout of the table is rejected at
+//! start-up, instead of the binding calling the wrong functions.  No entry may be null.  The rest of
+//! the binding calls the VM through `upcalls()`.
+
+use crate::abi::Klass;
+use crate::{FieldClosure, KlassLayoutClosure, MutatorClosure, SlotsClosure, WeakRootsClosure};
+use libc::{c_char, c_void};
+use mmtk::util::alloc::AllocationError;
+use mmtk::util::api_util::NullableObjectReference;
+use mmtk::util::opaque_pointer::*;
+use mmtk::util::ObjectReference;
+use once_cell::sync::OnceCell;
+use std::mem;
+
+/// The version of the layout of `OpenJDK_Upcalls`.  Bump it, together with `MMTK_UPCALLS_VERSION`
+/// in `mmtk.h`, whenever an upcall is added, removed or reordered, or changes its signature.
+pub const UPCALLS_VERSION: usize = 6;
+
+/// The size of the header before the entries
+const HEADER_SIZE: usize = 2 * mem::size_of::<usize>();
+
+macro_rules! define_upcalls {
+    ($($name:ident: $ty:ty,)*) => {
+        /// The table of upcalls.  The C++ side initializes it positionally, so the entries must be
+        /// in the same order as in `OpenJDK_Upcalls` in `mmtk.h`.
+        #[repr(C)]
+        pub struct OpenJDK_Upcalls {
+            /// `MMTK_UPCALLS_VERSION` of the C++ side
+            pub version: usize,
+            /// `sizeof(OpenJDK_Upcalls)` on the C++ side
+            pub size: usize,
+            $(pub $name: $ty,)*
+        }
+
+        /// The names of the entries, in order
+        const UPCALL_NAMES: &[&str] = &[$(stringify!($name)),*];
+    };
+}
+
+define_upcalls! {
+    stop_all_mutators: extern "C" fn(tls: VMWorkerThread, closure: MutatorClosure),
+    resume_mutators: extern "C" fn(tls: VMWorkerThread),
+    spawn_gc_thread: extern "C" fn(tls: VMThread, kind: libc::c_int, ctx: *mut libc::c_void),
+    block_for_gc: extern "C" fn(),
+    out_of_memory: extern "C" fn(tls: VMThread, err_kind: AllocationError),
+    get_mutators: extern "C" fn(closure: MutatorClosure),
+    scan_object: extern "C" fn(trace: *mut c_void, object: ObjectReference, tls: OpaquePointer),
+    dump_object: extern "C" fn(object: ObjectReference),
+    get_object_size: extern "C" fn(object: ObjectReference) -> usize,
+    get_mmtk_mutator: extern "C" fn(tls: VMMutatorThread) -> *mut libc::c_void,
+    is_mutator: extern "C" fn(tls: VMThread) -> bool,
+    harness_begin: extern "C" fn(),
+    harness_end: extern "C" fn(),
+    report_klass_mem_layout: extern "C" fn(closure: KlassLayoutClosure),
+    offset_of_static_fields: extern "C" fn() -> i32,
+    static_oop_field_count_offset: extern "C" fn() -> i32,
+    referent_offset: extern "C" fn() -> i32,
+    discovered_offset: extern "C" fn() -> i32,
+    next_offset: extern "C" fn() -> i32,
+    soft_reference_timestamp_offset: extern "C" fn() -> i32,
+    soft_reference_clock_offset: extern "C" fn() -> i32,
+    soft_reference_mirror: extern "C" fn() -> ObjectReference,
+    dump_object_string: extern "C" fn(object: ObjectReference) -> *const c_char,
+    scan_roots_in_all_mutator_threads: extern "C" fn(closure: SlotsClosure),
+    scan_roots_in_mutator_thread: extern "C" fn(closure: SlotsClosure, tls: VMMutatorThread),
+    scan_universe_roots: extern "C" fn(closure: SlotsClosure),
+    scan_jni_handle_roots: extern "C" fn(closure: SlotsClosure),
+    scan_object_synchronizer_roots: extern "C" fn(closure: SlotsClosure),
+    scan_management_roots: extern "C" fn(closure: SlotsClosure),
+    scan_jvmti_export_roots: extern "C" fn(closure: SlotsClosure),
+    scan_aot_loader_roots: extern "C" fn(closure: SlotsClosure),
+    scan_system_dictionary_roots: extern "C" fn(closure: SlotsClosure),
+    scan_code_cache_roots: extern "C" fn(closure: SlotsClosure),
+    scan_string_table_roots: extern "C" fn(closure: SlotsClosure),
+    process_string_table: extern "C" fn(closure: WeakRootsClosure),
+    scan_class_loader_data_graph_roots: extern "C" fn(closure: SlotsClosure),
+    scan_weak_processor_roots: extern "C" fn(closure: SlotsClosure),
+    process_weak_processor_roots: extern "C" fn(closure: WeakRootsClosure),
+    scan_vm_thread_roots: extern "C" fn(closure: SlotsClosure),
+    number_of_mutators: extern "C" fn() -> usize,
+    schedule_finalizer: extern "C" fn(),
+    prepare_for_roots_re_scanning: extern "C" fn(),
+    enqueue_references: extern "C" fn(objects: *const ObjectReference, len: usize),
+    mirror_klass_offset: extern "C" fn() -> i32,
+    klass_name: extern "C" fn(klass: *const Klass, buf: *mut c_char, len: usize) -> usize,
+    klass_class_loader: extern "C" fn(klass: *const Klass) -> NullableObjectReference,
+    iterate_klass_fields: extern "C" fn(klass: *const Klass, closure: FieldClosure),
+    sample_allocation: extern "C" fn(tls: VMMutatorThread, object: ObjectReference, bytes: usize),
+}
+
+const _: () = assert!(
+    mem::size_of::<OpenJDK_Upcalls>() == HEADER_SIZE + UPCALL_NAMES.len() * mem::size_of::<usize>()
+);
+
+static UPCALLS: OnceCell<&'static OpenJDK_Upcalls> = OnceCell::new();
+
+/// Check the header of the table at `calls`, and that none of its entries is null.
+pub(crate) fn check_upcalls(calls: *const OpenJDK_Upcalls) -> Result<(), String> {
+    if calls.is_null() {
+        return Err("The upcall table is null".to_string());
+    }
+    // Read the table as words.  Until we have checked them, the entries may not be valid function
+    // pointers, and the table may be shorter than we expect.
+    let words = calls as *const usize;
+    let version = unsafe { words.read() };
+    if version != UPCALLS_VERSION {
+        return Err(format!(
+            "The upcall table has version {}, but the binding expects version {}",
+            version, UPCALLS_VERSION
+        ));
+    }
+    let size = unsafe { words.add(1).read() };
+    if size != mem::size_of::<OpenJDK_Upcalls>() {
+        return Err(format!(
+            "The upcall table has {} bytes, but the binding expects {} bytes",
+            size,
+            mem::size_of::<OpenJDK_Upcalls>()
+        ));
+    }
+    let entries = unsafe { words.add(HEADER_SIZE / mem::size_of::<usize>()) };
+    let missing: Vec<&str> = UPCALL_NAMES
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| unsafe { entries.add(*i).read() } == 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("Missing upcalls: {}", missing.join(", ")));
+    }
+    Ok(())
+}
+
+/// Check and install the upcall table.  Panics with a description of the problem if the table is
+/// invalid.
+pub(crate) fn set_upcalls(calls: *const OpenJDK_Upcalls) {
+    if let Err(e) = check_upcalls(calls) {
+        panic!("{}", e);
+    }
+    let calls: &'static OpenJDK_Upcalls = unsafe { &*calls };
+    assert!(UPCALLS.set(calls).is_ok(), "The upcalls are already set");
+}
+
+/// Get the upcall table.  Panics if `openjdk_gc_init` has not been called.
+#[inline(always)]
+pub fn upcalls() -> &'static OpenJDK_Upcalls {
+    UPCALLS
+        .get()
+        .expect("The upcalls are used before openjdk_gc_init")
+}
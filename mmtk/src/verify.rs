@@ -0,0 +1,159 @@
+//! Heap verification.
+//!
+//! Starting from the roots of all categories, we compute the transitive closure of the object
+//! graph with `scan_object`, and check every non-null reference we find.  Java reference objects
+//! are treated as strong so that their referents are verified, too.  All mutators must be stopped.
+
+use crate::abi::Oop;
+use crate::object_model::{object_alignment, VMObjectModel};
+use crate::object_scanning::scan_object;
+use crate::roots::{for_each_root_category, RootCategory};
+use crate::OpenJDKSlot;
+use mmtk::memory_manager;
+use mmtk::util::opaque_pointer::{VMThread, VMWorkerThread};
+use mmtk::util::ObjectReference;
+use mmtk::vm::slot::Slot;
+use mmtk::vm::ObjectModel;
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Only report this many errors in detail.
+const MAX_REPORTED_ERRORS: usize = 20;
+
+thread_local! {
+    static VERIFYING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Return true if the current thread is verifying the heap.  `scan_object` does not discover
+/// references in this case.
+pub(crate) fn is_verifying() -> bool {
+    VERIFYING.with(|v| v.get())
+}
+
+/// Run `f` with `scan_object` treating references as strong on the current thread.
+pub(crate) fn with_references_as_strong<R>(f: impl FnOnce() -> R) -> R {
+    let was_verifying = VERIFYING.with(|v| v.replace(true));
+    let result = f();
+    VERIFYING.with(|v| v.set(was_verifying));
+    result
+}
+
+/// Where we found a reference.
+enum Location {
+    Root(RootCategory),
+    Field {
+        holder: ObjectReference,
+        offset: usize,
+    },
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Root(category) => write!(f, "root ({category})"),
+            Location::Field { holder, offset } => write!(f, "field {offset} of {holder}"),
+        }
+    }
+}
+
+struct HeapVerifier<const COMPRESSED: bool> {
+    visited: HashSet<ObjectReference>,
+    stack: Vec<ObjectReference>,
+    errors: usize,
+}
+
+impl<const COMPRESSED: bool> HeapVerifier<COMPRESSED> {
+    fn new() -> Self {
+        Self {
+            visited: HashSet::new(),
+            stack: vec![],
+            errors: 0,
+        }
+    }
+
+    fn check_object(object: ObjectReference) -> Result<(), &'static str> {
+        if !object.to_raw_address().is_aligned_to(object_alignment()) {
+            return Err("the object is not aligned");
+        }
+        if !memory_manager::is_in_mmtk_spaces(object) {
+            return Err("the object is not in MMTk spaces");
+        }
+        #[cfg(feature = "vo_bit")]
+        if memory_manager::is_mmtk_object(object.to_raw_address()).is_none() {
+            return Err("the VO bit of the object is not set");
+        }
+        if !Oop::from(object).has_klass::<COMPRESSED>() {
+            return Err("the klass pointer is null");
+        }
+        if !VMObjectModel::<COMPRESSED>::is_object_sane(object) {
+            return Err("the klass is not valid");
+        }
+        Ok(())
+    }
+
+    fn visit_slot(&mut self, slot: OpenJDKSlot<COMPRESSED>, location: Location) {
+        let Some(object) = slot.load() else {
+            return;
+        };
+        if !self.visited.insert(object) {
+            return;
+        }
+        match Self::check_object(object) {
+            Ok(()) => self.stack.push(object),
+            Err(reason) => {
+                self.errors += 1;
+                if self.errors <= MAX_REPORTED_ERRORS {
+                    log::error!(
+                        "Heap verification: bad reference {} in slot {} from {}: {}",
+                        object,
+                        slot.addr,
+                        location,
+                        reason
+                    );
+                }
+            }
+        }
+    }
+
+    fn verify(&mut self) {
+        for_each_root_category::<COMPRESSED>(|category, slots| {
+            for slot in slots {
+                self.visit_slot(slot, Location::Root(category));
+            }
+        });
+
+        let tls = VMWorkerThread(VMThread::UNINITIALIZED);
+        let mut slots = vec![];
+        while let Some(holder) = self.stack.pop() {
+            scan_object::<COMPRESSED>(
+                holder,
+                &mut |slot: OpenJDKSlot<COMPRESSED>| slots.push(slot),
+                tls,
+            );
+            for slot in slots.drain(..) {
+                let offset = slot.addr - holder.to_raw_address();
+                self.visit_slot(slot, Location::Field { holder, offset });
+            }
+        }
+    }
+}
+
+/// Verify all objects reachable from roots.  Returns the number of bad references.
+pub fn verify_heap<const COMPRESSED: bool>() -> usize {
+    let mut verifier = HeapVerifier::<COMPRESSED>::new();
+    with_references_as_strong(|| verifier.verify());
+
+    if verifier.errors > MAX_REPORTED_ERRORS {
+        log::error!(
+            "Heap verification: {} more bad references are not shown",
+            verifier.errors - MAX_REPORTED_ERRORS
+        );
+    }
+    log::debug!(
+        "Heap verification: {} objects verified, {} bad references",
+        verifier.visited.len(),
+        verifier.errors
+    );
+    verifier.errors
+}
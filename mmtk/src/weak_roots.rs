@@ -0,0 +1,46 @@
+//! Weak roots of the VM.
+//!
+//! Unlike strong roots, weak roots are not reported when the roots of a GC are scanned, and do
+//! not keep their objects alive.  After the transitive closure, the VM clears the weak roots whose
+//! objects are dead, and updates the others if their objects have moved.  There are two tables of
+//! weak roots, each processed by its own work packet (see `gc_work.rs`):
+//! -   the `StringTable` of interned strings, and
+//! -   the weak `OopStorage`s of the `WeakProcessor`, including JNI weak global references.
+//!
+//! The strong `OopStorage`s, e.g. JNI global references, are still scanned as strong roots.
+//!
+//! Weak roots are processed in the `VMRefClosure` stage, after soft, weak, final and phantom
+//! references, so objects resurrected for finalization keep their weak roots.  MarkCompact only
+//! computes the new addresses of objects in its second trace, so we update the weak roots again in
+//! the `VMRefForwarding` stage.
+
+use crate::gc_work::{ProcessStringTable, ProcessWeakProcessorRoots};
+use crate::{OpenJDK, WeakRootsClosure};
+use mmtk::memory_manager;
+use mmtk::scheduler::{GCWork, WorkBucketStage};
+use mmtk::util::ObjectReference;
+
+/// The (possibly moved) `object` if it is live, or `None` if it is dead.
+fn forwarded_if_live(object: ObjectReference) -> Option<ObjectReference> {
+    if object.is_reachable() {
+        Some(object.get_forwarded_object().unwrap_or(object))
+    } else {
+        None
+    }
+}
+
+/// Clear the dead entries of a table of weak roots with the upcall `process`, and update the live
+/// ones.
+pub(crate) fn process_weak_roots(process: extern "C" fn(closure: WeakRootsClosure)) {
+    let mut closure = forwarded_if_live;
+    process(WeakRootsClosure::from_rust_closure(&mut closure));
+}
+
+/// Add the packets that process the tables of weak roots to `stage`.
+pub(crate) fn schedule_weak_roots_processing<const COMPRESSED: bool>(stage: WorkBucketStage) {
+    let packets: Vec<Box<dyn GCWork<OpenJDK<COMPRESSED>>>> = vec![
+        Box::new(ProcessStringTable),
+        Box::new(ProcessWeakProcessorRoots),
+    ];
+    memory_manager::add_work_packets(crate::singleton::<COMPRESSED>(), stage, packets);
+}
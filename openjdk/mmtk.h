@@ -18,11 +18,90 @@ typedef enum {
     MmapOutOfMemory,
 } MMTkAllocationError;
 
+// This has the same layout as mmtk_openjdk::gc_stats::GCKind
+typedef enum {
+    MMTkNurseryGC,
+    MMTkFullGC,
+} MMTkGCKind;
+
+// This has the same layout as mmtk_openjdk::gc_stats::GCCause
+typedef enum {
+    MMTkHeapFull,
+    MMTkUserRequested,
+    MMTkPeriodic,
+} MMTkGCCause;
+
+// This has the same layout as mmtk_openjdk::gc_stats::GCStats.
+// Times are in nanoseconds, and timestamps are comparable with os::javaTimeNanos().
+typedef struct {
+    uint64_t gc_id;
+    MMTkGCKind kind;
+    const char* plan;
+    uint64_t pause_start;
+    uint64_t pause_end;
+    size_t used_bytes_before;
+    size_t used_bytes_after;
+    uint64_t stop_mutators_time;
+    uint64_t root_scanning_time;
+    size_t references_discovered;
+    size_t references_enqueued;
+    size_t finalizers_registered;
+    MMTkGCCause cause;
+    size_t committed_bytes_decrease;
+    size_t released_bytes;
+} MMTkGCStats;
+
+// This has the same layout as mmtk_openjdk::gc_stats::GCCollectionCounts.
+// Times are in nanoseconds.
+typedef struct {
+    uint64_t nursery_count;
+    uint64_t nursery_time;
+    uint64_t full_count;
+    uint64_t full_time;
+} MMTkCollectionCounts;
+
+// This has the same layout as mmtk_openjdk::gc_stats::SpaceUsage.
+// Sizes are in bytes.  `reserved` is 0 if the space shares the address range of the heap.
+typedef struct {
+    const char* name;
+    size_t reserved;
+    size_t committed;
+    size_t used;
+} MMTkSpaceUsage;
+
+// This has the same layout as mmtk_openjdk::gc_listener::GCEvent.
+// The usage arrays are only valid during the call to the listener.
+typedef struct {
+    uint64_t gc_id;
+    MMTkGCKind kind;
+    MMTkGCCause cause;
+    uint64_t pause_start;
+    uint64_t duration;
+    size_t num_spaces;
+    const MMTkSpaceUsage* usage_before;
+    const MMTkSpaceUsage* usage_after;
+} MMTkGCEvent;
+
+typedef struct {
+    void (*func)(const MMTkGCEvent* event, void* data);
+    void* data;
+} MMTkGCListener;
+
+// This has the same layout as mmtk_openjdk::tlab::TLAB.
+// The bump-pointer buffer of the default allocator of a mutator.  All fields are NULL if there is none.
+typedef struct {
+    void* start;
+    void* top;
+    void* end;
+} MMTkTLAB;
+
 extern const uintptr_t GLOBAL_SIDE_METADATA_BASE_ADDRESS;
 extern const uintptr_t GLOBAL_SIDE_METADATA_VM_BASE_ADDRESS;
 extern const uintptr_t VO_BIT_ADDRESS;
 extern const size_t MMTK_MARK_COMPACT_HEADER_RESERVED_IN_BYTES;
 extern const uintptr_t FREE_LIST_ALLOCATOR_SIZE;
+// The size of the Rust Mutator struct, and the offset of the fields after it in MMTkMutatorContext
+extern const uintptr_t MUTATOR_SIZE;
 extern uint8_t CONCURRENT_MARKING_ACTIVE;
 
 extern const char* get_mmtk_version();
@@ -33,9 +112,16 @@ extern const char* get_mmtk_version();
 extern MMTk_Mutator bind_mutator(void *tls);
 extern void destroy_mutator(MMTk_Mutator mutator);
 extern void flush_mutator(MMTk_Mutator mutator);
-
+extern MMTkTLAB mmtk_get_tlab(MMTk_Mutator mutator);
+// Sample one object every `bytes` bytes allocated by each mutator.  0 disables sampling.
+extern void mmtk_set_allocation_sampling_interval(size_t bytes);
+
+// `site` identifies the allocation site for pretenuring, or is MMTK_NO_ALLOCATION_SITE.
+// Select the allocator once per object, and pass it to both alloc and post_alloc.
+#define MMTK_NO_ALLOCATION_SITE 0
+extern int mmtk_select_allocation_semantics(int allocator, size_t site);
 extern void* alloc(MMTk_Mutator mutator, size_t size,
-    size_t align, size_t offset, int allocator);
+    size_t align, size_t offset, int allocator, size_t site);
 
 extern void* alloc_slow_bump_monotone_immortal(MMTk_Mutator mutator, size_t size,
     size_t align, size_t offset);
@@ -46,6 +132,9 @@ extern void* alloc_slow_largeobject(MMTk_Mutator mutator, size_t size,
 
 extern void post_alloc(MMTk_Mutator mutator, void* refer,
     size_t bytes, int allocator);
+// Pretenure allocation sites whose survival rate (in percent) exceeds this.  0 disables pretenuring.
+extern void mmtk_set_pretenure_threshold(size_t percent);
+extern void mmtk_set_soft_ref_lru_policy_ms_per_mb(size_t ms_per_mb);
 
 /// java.lang.Reference load barrier
 extern void mmtk_load_reference(MMTk_Mutator mutator, void* obj);
@@ -92,7 +181,6 @@ extern size_t get_max_non_los_default_alloc_bytes();
 /**
  * Finalization
  */
-extern void add_finalizer(void* obj);
 extern void* get_finalized_object();
 
 /**
@@ -143,10 +231,56 @@ struct SlotsClosure {
     }
 };
 
+struct HeapObjectClosure {
+    void (*func)(void* object, void* data);
+    void* data;
+
+    void invoke(void* object) {
+        func(object, data);
+    }
+};
+
+struct FieldClosure {
+    void (*func)(const char* name, int basic_type, int offset, bool is_static, void* data);
+    void* data;
+
+    void invoke(const char* name, int basic_type, int offset, bool is_static) {
+        func(name, basic_type, offset, is_static, data);
+    }
+};
+
+// Returns the (possibly moved) object if `object` is live, or NULL if it is dead.
+struct WeakRootsClosure {
+    void* (*func)(void* object, void* data);
+    void* data;
+
+    void* invoke(void* object) {
+        return func(object, data);
+    }
+};
+
+// `value` is a struct size or a field offset if `klass` is NULL, or a value read from `klass`.
+struct KlassLayoutClosure {
+    void (*func)(const char* name, intptr_t value, void* klass, void* data);
+    void* data;
+
+    void invoke(const char* name, intptr_t value, void* klass) {
+        func(name, value, klass, data);
+    }
+};
+
 /**
  * OpenJDK-specific
  */
+
+// The version of the layout of OpenJDK_Upcalls.  Bump it together with UPCALLS_VERSION in
+// mmtk/src/upcalls.rs whenever an upcall is added, removed, reordered or changes its signature.
+#define MMTK_UPCALLS_VERSION 6
+
 typedef struct {
+    // MMTK_UPCALLS_VERSION and sizeof(OpenJDK_Upcalls), checked by openjdk_gc_init
+    size_t version;
+    size_t size;
     void (*stop_all_mutators) (void *tls, MutatorClosure closure);
     void (*resume_mutators) (void *tls);
     void (*spawn_gc_thread) (void *tls, int kind, void *ctx);
@@ -160,11 +294,15 @@ typedef struct {
     bool (*is_mutator) (void* tls);
     void (*harness_begin) ();
     void (*harness_end) ();
-    size_t (*compute_klass_mem_layout_checksum) ();
+    void (*report_klass_mem_layout) (KlassLayoutClosure closure);
     int (*offset_of_static_fields) ();
     int (*static_oop_field_count_offset) ();
     int (*referent_offset) ();
     int (*discovered_offset) ();
+    int (*next_offset) ();
+    int (*soft_reference_timestamp_offset) ();
+    int (*soft_reference_clock_offset) ();
+    void* (*soft_reference_mirror) ();
     char* (*dump_object_string) (void* object);
     void (*scan_roots_in_all_mutator_threads)(SlotsClosure closure);
     void (*scan_roots_in_mutator_thread)(SlotsClosure closure, void* tls);
@@ -177,26 +315,64 @@ typedef struct {
     void (*scan_system_dictionary_roots) (SlotsClosure closure);
     void (*scan_code_cache_roots) (SlotsClosure closure);
     void (*scan_string_table_roots) (SlotsClosure closure);
+    void (*process_string_table) (WeakRootsClosure closure);
     void (*scan_class_loader_data_graph_roots) (SlotsClosure closure);
     void (*scan_weak_processor_roots) (SlotsClosure closure);
+    void (*process_weak_processor_roots) (WeakRootsClosure closure);
     void (*scan_vm_thread_roots) (SlotsClosure closure);
     size_t (*number_of_mutators)();
     void (*schedule_finalizer)();
     void (*prepare_for_roots_re_scanning)();
     void (*enqueue_references)(void** objects, size_t len);
+    int (*mirror_klass_offset)();
+    size_t (*klass_name)(void* klass, char* buf, size_t len);
+    void* (*klass_class_loader)(void* klass);
+    void (*iterate_klass_fields)(void* klass, FieldClosure closure);
+    void (*sample_allocation)(void* tls, void* object, size_t bytes);
 } OpenJDK_Upcalls;
 
 extern void openjdk_gc_init(OpenJDK_Upcalls *calls);
 extern bool openjdk_is_gc_initialized();
+// Check the memory layout of klasses against the binding.  Call it after the well-known classes are loaded.
+extern void mmtk_validate_memory_layouts();
 
-extern bool mmtk_set_heap_size(size_t min, size_t max);
+// If `resizable` is true, the limits can be changed later with mmtk_set_heap_limits.
+extern bool mmtk_set_heap_size(size_t min, size_t max, bool resizable);
+// Change the heap size limits at run time.  Returns false if the heap is not resizable, or if the
+// limits are invalid for the reserved heap.
+extern bool mmtk_set_heap_limits(size_t soft_max, size_t max);
 
+// Set the object alignment to ObjectAlignmentInBytes.  Must be called before openjdk_gc_init.
+extern void mmtk_set_object_alignment(size_t bytes);
 extern bool mmtk_enable_compressed_oops();
 extern void* mmtk_narrow_oop_base();
 extern size_t mmtk_narrow_oop_shift();
 extern size_t mmtk_set_compressed_klass_base_and_shift(void* base, size_t shift);
 
 extern size_t used_bytes();
+extern bool mmtk_iterate_objects(HeapObjectClosure closure);
+extern bool mmtk_dump_heap(const char* path);
+// Must be called after unloading classes.  See mmtk/src/object_scanning.rs.
+extern void mmtk_invalidate_scan_plans();
+// Find the start of the live object that contains `addr`, or NULL.  Needs the vo_bit feature.
+// Outside the large object space, only objects starting at most `max_search_bytes` before `addr`
+// are found.
+extern void* mmtk_find_object_from_interior_pointer(void* addr, size_t max_search_bytes);
+extern void* mmtk_block_start(void* addr);
+extern bool mmtk_is_object_start(void* addr);
+extern size_t mmtk_get_object_size(void* object);
+extern size_t mmtk_verify_heap();
+extern size_t mmtk_get_gc_stats(MMTkGCStats* buf, size_t max);
+extern size_t mmtk_get_space_usage(MMTkSpaceUsage* buf, size_t max);
+extern MMTkCollectionCounts mmtk_get_collection_counts();
+// Periodic GC.  The interval is in milliseconds, and 0 disables it.
+extern void mmtk_set_periodic_gc_interval(uint64_t interval_ms);
+extern uint64_t mmtk_periodic_gc_interval();
+extern uint64_t mmtk_periodic_gc_poll(void* tls);
+extern void mmtk_add_gc_listener(MMTkGCListener listener);
+extern void mmtk_remove_gc_listener(MMTkGCListener listener);
+extern bool mmtk_is_generational_plan();
+extern bool mmtk_is_current_gc_nursery();
 extern void* starting_heap_address();
 extern void* last_heap_address();
 extern void iterator(); // ???
@@ -140,6 +140,12 @@ void MMTkBarrierSetAssembler::eden_allocate(MacroAssembler* masm, Register threa
   }
 }
 
+// With UseTLAB, the interpreter and C1 allocate with tlab_allocate.  The MMTk allocation buffers
+// are our TLABs, so this is the same as eden_allocate.
+void MMTkBarrierSetAssembler::tlab_allocate(MacroAssembler* masm, Register thread, Register obj, Register var_size_in_bytes, int con_size_in_bytes, Register t1, Register t2, Label& slow_case) {
+  eden_allocate(masm, thread, obj, var_size_in_bytes, con_size_in_bytes, t1, slow_case);
+}
+
 #undef __
 
 //////////////////// Assembler for C1 ////////////////////
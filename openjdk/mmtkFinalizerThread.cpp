@@ -23,12 +23,16 @@
  */
 
 #include "precompiled.hpp"
+#include "classfile/javaClasses.hpp"
 #include "classfile/stringTable.hpp"
 #include "classfile/symbolTable.hpp"
 #include "mmtk.h"
+#include "memory/universe.hpp"
 #include "mmtkFinalizerThread.hpp"
+#include "oops/access.inline.hpp"
 #include "oops/oop.inline.hpp"
 #include "prims/jvmtiImpl.hpp"
+#include "runtime/handles.inline.hpp"
 #include "runtime/interfaceSupport.inline.hpp"
 #include "runtime/javaCalls.hpp"
 #include "runtime/mutex.hpp"
@@ -95,29 +99,32 @@ void MMTkFinalizerThread::finalizer_thread_entry(JavaThread* thread, TRAPS) {
       this_thread->is_scheduled = false; // Consume this request so we can accept the next.
     }
 
-    // finalize objects
+    // Hand the FinalReferences of finalizable objects to java.lang.ref.Finalizer, which runs
+    // the finalizers.
     while (true) {
-      void* obj_ref = get_finalized_object();
-      if (obj_ref != NULL) {
-        instanceOop obj = (instanceOop) obj_ref;
-
-        // Invoke finalize()
-        {
-          HandleMark hm;
-          JavaValue ret(T_VOID);
-          instanceHandle handle_obj(this_thread, obj);
-          TempNewSymbol finalize_method = SymbolTable::new_symbol("finalize", this_thread);
-          Symbol* sig = vmSymbols::void_method_signature();
-
-          JavaCalls::call_virtual(&ret, handle_obj, obj->klass(), finalize_method, sig, this_thread);
-        }
-      } else {
+      void* reference = get_finalized_object();
+      if (reference == NULL) {
         break;
       }
+      enqueue_final_reference(this_thread, (oop) reference);
     }
   }
 }
 
+// Put a FinalReference whose referent is ready for finalization into the reference pending list,
+// and wake up the ReferenceHandler thread.
+void MMTkFinalizerThread::enqueue_final_reference(JavaThread* thread, oop reference) {
+  HandleMark hm(thread);
+  Handle handle(thread, reference);
+  MonitorLockerEx ml(Heap_lock);
+  // Make the reference inactive so that it is not discovered again.  Its referent stays
+  // strongly reachable until the Finalizer has run the finalizer and cleared it.
+  HeapAccess<AS_RAW>::oop_store_at(handle(), java_lang_ref_Reference::next_offset, handle());
+  oop old_first = Universe::swap_reference_pending_list(handle());
+  HeapAccess<AS_RAW>::oop_store_at(handle(), java_lang_ref_Reference::discovered_offset, old_first);
+  ml.notify_all();
+}
+
 MMTkFinalizerThread::MMTkFinalizerThread(ThreadFunction entry_point) : JavaThread(entry_point) {
   this->is_scheduled = false;
   this->m = new Monitor(Mutex::suspend_resume, "mmtk-finalizer-monitor", true, Monitor::_safepoint_check_never);
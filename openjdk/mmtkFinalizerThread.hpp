@@ -46,6 +46,7 @@ public:
   static MMTkFinalizerThread* instance;
   static void initialize();
   static void finalizer_thread_entry(JavaThread* thread, TRAPS);
+  static void enqueue_final_reference(JavaThread* thread, oop reference);
 
   void schedule();
 };
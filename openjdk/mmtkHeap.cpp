@@ -36,13 +36,17 @@
 #include "mmtk.h"
 #include "mmtkHeap.hpp"
 #include "mmtkMutator.hpp"
+#include "mmtkPeriodicGCThread.hpp"
 #include "mmtkUpcalls.hpp"
 #include "mmtkVMCompanionThread.hpp"
 #include "oops/oop.inline.hpp"
+#include "prims/jvmtiExport.hpp"
 #include "runtime/atomic.hpp"
 #include "runtime/handles.inline.hpp"
 #include "runtime/java.hpp"
+#include "runtime/safepoint.hpp"
 #include "runtime/thread.hpp"
+#include "runtime/threadHeapSampler.hpp"
 #include "runtime/vmThread.hpp"
 #include "services/management.hpp"
 #include "services/memoryManager.hpp"
@@ -69,12 +73,33 @@ MMTkHeap::MMTkHeap(MMTkCollectorPolicy* policy) :
   _collector_policy(policy),
   _num_root_scan_tasks(0),
   _n_workers(0),
+  _verify_before_gc(false),
+  _verify_after_gc(false),
+  _num_mmtk_pools(0),
+  _mmtk_nursery_manager(NULL),
+  _mmtk_full_manager(NULL),
+  _mmtk_current_manager(NULL),
+  _requested_gc_cause(GCCause::_java_lang_system_gc),
   _gc_lock(new Monitor(Mutex::safepoint, "MMTkHeap::_gc_lock", true, Monitor::_safepoint_check_never)),
   _soft_ref_policy()
 {
   _heap = this;
 }
 
+static void set_uint64_option_from_env_var(const char *name, uint64_t *var) {
+  const char *env_var = getenv(name);
+  if (env_var != NULL) {
+    char *end;
+    errno = 0;
+    unsigned long long value = strtoull(env_var, &end, 10);
+    if (errno != 0 || end == env_var || *end != '\0') {
+      fprintf(stderr, "Unexpected value for env var %s: %s\n", name, env_var);
+      abort();
+    }
+    *var = (uint64_t) value;
+  }
+}
+
 static void set_bool_option_from_env_var(const char *name, bool *var) {
   const char *env_var = getenv(name);
   if (env_var != NULL) {
@@ -89,13 +114,52 @@ static void set_bool_option_from_env_var(const char *name, bool *var) {
   }
 }
 
-jint MMTkHeap::initialize() {
-  assert(!UseTLAB , "should disable UseTLAB");
+// MMTK_ALLOCATION_SAMPLING_INTERVAL, which overrides the JVMTI heap sampling interval, or 0
+static uint64_t _allocation_sampling_interval_override = 0;
+// The sampling interval we last passed to MMTk
+static size_t _allocation_sampling_interval = 0;
+
+// JVMTI may enable heap sampling or change its interval at any time, so we check the settings of
+// ThreadHeapSampler in every allocation slow path, and pass them to MMTk when they change.
+static void update_allocation_sampling_interval() {
+  size_t interval;
+  if (_allocation_sampling_interval_override != 0) {
+    interval = (size_t) _allocation_sampling_interval_override;
+  } else if (ThreadHeapSampler::enabled()) {
+    // For JVMTI, an interval of 0 samples every allocation.  For MMTk, 0 disables sampling.
+    interval = MAX2((size_t) ThreadHeapSampler::get_sampling_interval(), (size_t) 1);
+  } else {
+    interval = 0;
+  }
+  if (interval != _allocation_sampling_interval) {
+    _allocation_sampling_interval = interval;
+    mmtk_set_allocation_sampling_interval(interval);
+  }
+}
 
+jint MMTkHeap::initialize() {
   set_bool_option_from_env_var("MMTK_ENABLE_ALLOCATION_FASTPATH", &mmtk_enable_allocation_fastpath);
   set_bool_option_from_env_var("MMTK_ENABLE_BARRIER_FASTPATH", &mmtk_enable_barrier_fastpath);
   set_bool_option_from_env_var("MMTK_ENABLE_REFERENCE_LOAD_BARRIER", &mmtk_enable_reference_load_barrier);
 
+  _verify_before_gc = VerifyBeforeGC;
+  _verify_after_gc = VerifyAfterGC;
+  set_bool_option_from_env_var("MMTK_VERIFY_BEFORE_GC", &_verify_before_gc);
+  set_bool_option_from_env_var("MMTK_VERIFY_AFTER_GC", &_verify_after_gc);
+
+  uint64_t periodic_gc_interval = 0;
+  set_uint64_option_from_env_var("MMTK_PERIODIC_GC_INTERVAL", &periodic_gc_interval);
+  mmtk_set_periodic_gc_interval(periodic_gc_interval);
+
+  set_uint64_option_from_env_var("MMTK_ALLOCATION_SAMPLING_INTERVAL", &_allocation_sampling_interval_override);
+  update_allocation_sampling_interval();
+
+  uint64_t pretenure_threshold = 0;
+  set_uint64_option_from_env_var("MMTK_PRETENURE_THRESHOLD", &pretenure_threshold);
+  mmtk_set_pretenure_threshold((size_t) pretenure_threshold);
+
+  mmtk_set_soft_ref_lru_policy_ms_per_mb((size_t) SoftRefLRUPolicyMSPerMB);
+
   if (!mmtk_enable_reference_load_barrier) {
     fprintf(stderr,
       "WARNING! Reference load barrier is disabled.  "
@@ -107,6 +171,8 @@ jint MMTkHeap::initialize() {
   const size_t max_heap_size = collector_policy()->max_heap_byte_size();
   //  printf("policy max heap size %zu, min heap size %zu\n", heap_size, collector_policy()->min_heap_byte_size());
 
+  // With compressed oops, the alignment also determines the shift, and how large the heap can be.
+  mmtk_set_object_alignment(ObjectAlignmentInBytes);
   if (UseCompressedOops) mmtk_enable_compressed_oops();
 
   // Note that MMTk options may be set from several different sources, with increasing priorities:
@@ -131,8 +197,10 @@ jint MMTkHeap::initialize() {
     guarantee(set_options, "Failed to set MMTk options. Please check if the options are valid: %s\n", ThirdPartyHeapOptions);
   }
 
-  // Set heap size
-  bool set_heap_size = mmtk_set_heap_size(min_heap_size, max_heap_size);
+  // Set heap size.  The heap limits can only be changed at run time if asked for.
+  bool resizable_heap = false;
+  set_bool_option_from_env_var("MMTK_RESIZABLE_HEAP", &resizable_heap);
+  bool set_heap_size = mmtk_set_heap_size(min_heap_size, max_heap_size, resizable_heap);
   guarantee(set_heap_size, "Failed to set MMTk heap size. Please check if the heap size is valid: min = %ld, max = %ld\n", min_heap_size, max_heap_size);
 
   openjdk_gc_init(&mmtk_upcalls);
@@ -203,6 +271,8 @@ void MMTkHeap::schedule_finalizer() {
 
 void MMTkHeap::post_initialize() {
   CollectedHeap::post_initialize();
+  // The well-known classes are loaded by now.
+  mmtk_validate_memory_layouts();
   if (UseCompressedOops) {
     mmtk_set_compressed_klass_base_and_shift((void*) Universe::narrow_klass_base(), (size_t) Universe::narrow_klass_shift());
   }
@@ -214,6 +284,10 @@ void MMTkHeap::enable_collection() {
   MMTkFinalizerThread::initialize();
 
   ::initialize_collection(0);
+
+  if (mmtk_periodic_gc_interval() != 0) {
+    MMTkPeriodicGCThread::initialize();
+  }
 }
 
 ////Previously pure abstract methods--
@@ -270,25 +344,44 @@ bool MMTkHeap::is_in_reserved(const void* p) const {
   return is_in(p);
 }
 
+// MMTk allocates with its own thread-local bump-pointer buffers, which we report as TLABs.
+// HotSpot never allocates a TLAB itself (see unsafe_max_tlab_alloc), so thread->tlab() stays
+// empty and allocation falls through to the MMTk fast path or mem_allocate.
 bool MMTkHeap::supports_tlab_allocation() const {
-  //returning false is good enough...used in universe.cpp
-  return false;
+  return true;
+}
+
+static MMTkTLAB mutator_tlab(Thread* thr) {
+  if (!thr->is_Java_thread() || !MMTkMutatorContext::is_ready_to_bind()) {
+    MMTkTLAB empty = { NULL, NULL, NULL };
+    return empty;
+  }
+  return thr->third_party_heap_mutator.tlab();
 }
 
 // The amount of space available for thread-local allocation buffers.
 size_t MMTkHeap::tlab_capacity(Thread *thr) const {
-  //no need to further implement but we need UseTLAB=False
-  guarantee(false, "tlab_capacity not supported");
-  return 0;
+  MMTkTLAB tlab = mutator_tlab(thr);
+  return pointer_delta(tlab.end, tlab.start, 1);
 }
 
 // The amount of used space for thread-local allocation buffers for the given thread.
 size_t MMTkHeap::tlab_used(Thread *thr) const {
-  //no need to further implement but we need UseTLAB=False
-  guarantee(false, "tlab_used not supported");
+  MMTkTLAB tlab = mutator_tlab(thr);
+  return pointer_delta(tlab.top, tlab.start, 1);
+}
+
+// HotSpot must not carve its own TLABs out of the MMTk heap, so only tlab_capacity and tlab_used
+// above report the MMTk buffer, and HotSpot's TLAB stays empty.  Returning 0 makes
+// ThreadLocalAllocBuffer::compute_size() fail, and the allocation goes to mem_allocate.
+size_t MMTkHeap::unsafe_max_tlab_alloc(Thread *thr) const {
   return 0;
 }
 
+HeapWord* MMTkHeap::allocate_new_tlab(size_t min_size, size_t requested_size, size_t* actual_size) {
+  return NULL;
+}
+
 
 // Can a compiler initialize a new object without store barriers?
 // This permission only extends from the creation of a new object
@@ -309,6 +402,7 @@ bool MMTkHeap::card_mark_must_follow_store() const { //OK
 }
 
 void MMTkHeap::collect(GCCause::Cause cause) {//later when gc is implemented in rust
+  _requested_gc_cause = cause;
   handle_user_collection_request((MMTk_Mutator) &Thread::current()->third_party_heap_mutator);
   // guarantee(false, "collect not supported");
 }
@@ -328,41 +422,58 @@ SoftRefPolicy* MMTkHeap::soft_ref_policy() {return &_soft_ref_policy;}//OK
 
 GrowableArray<GCMemoryManager*> MMTkHeap::memory_managers() {//may cause error
 
-  GrowableArray<GCMemoryManager*> memory_managers(1);
-  memory_managers.append(_mmtk_manager);
+  GrowableArray<GCMemoryManager*> memory_managers(2);
+  if (_mmtk_nursery_manager != NULL) {
+    memory_managers.append(_mmtk_nursery_manager);
+  }
+  memory_managers.append(_mmtk_full_manager);
   return memory_managers;
 }
 GrowableArray<MemoryPool*> MMTkHeap::memory_pools() {//may cause error
 
-  GrowableArray<MemoryPool*> memory_pools(1);
-  memory_pools.append(_mmtk_pool);
+  GrowableArray<MemoryPool*> memory_pools((int) _num_mmtk_pools);
+  for (size_t i = 0; i < _num_mmtk_pools; i++) {
+    memory_pools.append(_mmtk_pools[i]);
+  }
   return memory_pools;
 }
 
-// Iterate over all objects, calling "cl.do_object" on each.
-void MMTkHeap::object_iterate(ObjectClosure* cl) { //No need to implement.Traced whole path.Only other heaps call it.
-  fprintf(stderr, "WARNING: MMTkHeap::object_iterate is not implemented, yet.\n");
+static void mmtk_do_object(void* object, void* data) {
+  ((ObjectClosure*) data)->do_object((oop) object);
+}
+
+// Iterate over all objects, calling "cl.do_object" on each.  MMTk enumerates objects with the VO
+// bits, so objects that became unreachable since the last GC may be reported, too.
+void MMTkHeap::object_iterate(ObjectClosure* cl) {
+  assert(SafepointSynchronize::is_at_safepoint(), "Object iteration must be done at a safepoint");
+  HeapObjectClosure closure = { mmtk_do_object, (void*) cl };
+  if (!mmtk_iterate_objects(closure)) {
+    fprintf(stderr, "WARNING: MMTkHeap::object_iterate requires the vo_bit feature of the MMTk binding.\n");
+  }
 }
 
 // Similar to object_iterate() except iterates only
 // over live objects.
-void MMTkHeap::safe_object_iterate(ObjectClosure* cl) { //not sure..many dependencies from vm
-  fprintf(stderr, "WARNING: MMTkHeap::safe_object_iterate is not implemented, yet.\n");
+// We are already in a safepoint, and cannot trigger a GC here, so this is the same as
+// object_iterate, and may report objects that became unreachable since the last GC.  Callers that
+// need precise liveness must collect the heap first.
+void MMTkHeap::safe_object_iterate(ObjectClosure* cl) {
+  object_iterate(cl);
 }
 
+// The "blocks" of MMTkHeap are objects.  We find them using the VO bits, so these functions need
+// the vo_bit feature of the MMTk binding.  Without it, block_start always returns NULL.
 HeapWord* MMTkHeap::block_start(const void* addr) const {//OK
-  guarantee(false, "block start not supported");
-  return NULL;
+  return (HeapWord*) mmtk_block_start((void*) addr);
 }
 
 size_t MMTkHeap::block_size(const HeapWord* addr) const { //OK
-  guarantee(false, "block size not supported");
-  return 0;
+  assert(block_is_obj(addr), "addr must be the start of an object");
+  return mmtk_get_object_size((void*) addr) / HeapWordSize;
 }
 
 bool MMTkHeap::block_is_obj(const HeapWord* addr) const { //OK
-  guarantee(false, "block is obj not supported");
-  return false;
+  return mmtk_is_object_start((void*) addr);
 }
 
 jlong MMTkHeap::millis_since_last_gc() {//later when gc is implemented in rust
@@ -377,17 +488,93 @@ jlong MMTkHeap::millis_since_last_gc() {//later when gc is implemented in rust
 
 
 void MMTkHeap::prepare_for_verify() {
-  // guarantee(false, "prepare for verify not supported");
+  // Nothing to do.  The verifier does not depend on any state prepared by the heap.
 }
 
+void MMTkHeap::verify_heap(const char* when) {
+  log_info(gc, verify)("Verifying heap %s", when);
+  size_t errors = mmtk_verify_heap();
+  guarantee(errors == 0, "Heap verification %s found " SIZE_FORMAT " bad references", when, errors);
+}
+
+
+static void mmtk_gc_listener(const MMTkGCEvent* event, void* data) {
+  ((MMTkHeap*) data)->gc_end_serviceability(event);
+}
 
 void MMTkHeap::initialize_serviceability() {//OK
+  // One pool for each space of the MMTk plan.
+  MMTkSpaceUsage spaces[MMTK_MAX_MEMORY_POOLS];
+  size_t n = mmtk_get_space_usage(spaces, MMTK_MAX_MEMORY_POOLS);
+  if (n > MMTK_MAX_MEMORY_POOLS) {
+    log_warning(gc)("The MMTk plan has " SIZE_FORMAT " spaces. Only the first %d are shown as memory pools.",
+                    n, MMTK_MAX_MEMORY_POOLS);
+    n = MMTK_MAX_MEMORY_POOLS;
+  }
+  for (size_t i = 0; i < n; i++) {
+    // Discontiguous spaces share the address range of the heap.
+    size_t max_size = spaces[i].reserved != 0 ? MIN2(spaces[i].reserved, max_capacity()) : max_capacity();
+    _mmtk_pools[i] = new MMTkMemoryPool(i, spaces[i].name, 0, max_size, false);
+  }
+  _num_mmtk_pools = n;
+
+  // One manager for each kind of GC.  Every GC may affect all the spaces.
+  if (mmtk_is_generational_plan()) {
+    _mmtk_nursery_manager = new GCMemoryManager("MMTk Nursery GC", "end of minor GC");
+  }
+  _mmtk_full_manager = new GCMemoryManager("MMTk Full GC", "end of major GC");
+  for (size_t i = 0; i < _num_mmtk_pools; i++) {
+    if (_mmtk_nursery_manager != NULL) {
+      _mmtk_nursery_manager->add_pool(_mmtk_pools[i]);
+    }
+    _mmtk_full_manager->add_pool(_mmtk_pools[i]);
+  }
+
+  MMTkGCListener listener = { mmtk_gc_listener, (void*) this };
+  mmtk_add_gc_listener(listener);
+}
+
+void MMTkHeap::gc_begin_serviceability() {
+  bool is_nursery = _mmtk_nursery_manager != NULL && mmtk_is_current_gc_nursery();
+  _mmtk_current_manager = is_nursery ? _mmtk_nursery_manager : _mmtk_full_manager;
+  _mmtk_current_manager->gc_begin(true /* recordGCBeginTime */,
+                                  true /* recordPreGCUsage */,
+                                  true /* recordAccumulatedGCTime */);
+}
 
+void MMTkHeap::gc_end_serviceability(const MMTkGCEvent* event) {
+  assert(_mmtk_current_manager != NULL, "gc_begin_serviceability must be called first");
+  GCCause::Cause cause;
+  switch (event->cause) {
+  case MMTkUserRequested: cause = _requested_gc_cause; break;
+  // JDK 11 has no GC cause for periodic GCs.
+  case MMTkPeriodic: cause = GCCause::_no_cause_specified; break;
+  default: cause = GCCause::_allocation_failure; break;
+  }
 
-  _mmtk_pool = new MMTkMemoryPool(_start, _end,  "MMTk pool",collector_policy()->min_heap_byte_size(), false);
+  size_t used_before = 0;
+  size_t used_after = 0;
+  for (size_t i = 0; i < event->num_spaces; i++) {
+    const MMTkSpaceUsage* before = &event->usage_before[i];
+    const MMTkSpaceUsage* after = &event->usage_after[i];
+    log_debug(gc, heap)("GC(" UINT64_FORMAT ") %s: " SIZE_FORMAT "K->" SIZE_FORMAT "K",
+                        event->gc_id, before->name, before->used / K, after->used / K);
+    used_before += before->used;
+    used_after += after->used;
+  }
+  log_info(gc)("GC(" UINT64_FORMAT ") Pause %s (%s) " SIZE_FORMAT "M->" SIZE_FORMAT "M %.3fms",
+               event->gc_id, event->kind == MMTkNurseryGC ? "Nursery" : "Full", GCCause::to_string(cause),
+               used_before / M, used_after / M, (double) event->duration / NANOSECS_PER_MILLISEC);
 
-  _mmtk_manager = new GCMemoryManager("MMTk GC", "end of GC");
-  _mmtk_manager->add_pool(_mmtk_pool);
+  // The memory managers read the usage after GC from the memory pools, and send a
+  // GarbageCollectionNotificationInfo if notification is enabled.
+  _mmtk_current_manager->gc_end(true /* recordPostGCUsage */,
+                                true /* recordAccumulatedGCTime */,
+                                true /* recordGCEndTime */,
+                                true /* countCollection */,
+                                cause,
+                                true /* allMemoryPoolsAffected */);
+  _mmtk_current_manager = NULL;
 }
 
 // Print heap information on the given outputStream.
@@ -406,7 +593,11 @@ void MMTkHeap::gc_threads_do(ThreadClosure* tc) const {
 // Print any relevant tracing info that flags imply.
 // Default implementation does nothing.
 void MMTkHeap::print_tracing_info() const {
-  //guarantee(false, "print tracing info not supported");
+  MMTkCollectionCounts counts = mmtk_get_collection_counts();
+  log_debug(gc, heap, exit)("Accumulated nursery GC time %3.7f secs, " UINT64_FORMAT " GCs",
+                            (double) counts.nursery_time / NANOSECS_PER_SEC, counts.nursery_count);
+  log_debug(gc, heap, exit)("Accumulated full GC time %3.7f secs, " UINT64_FORMAT " GCs",
+                            (double) counts.full_time / NANOSECS_PER_SEC, counts.full_count);
 }
 
 
@@ -441,7 +632,10 @@ void MMTkHeap::unregister_nmethod(nmethod* nm) {
 }
 
 // Heap verification
-void MMTkHeap::verify(VerifyOption option) {}
+void MMTkHeap::verify(VerifyOption option) {
+  assert(SafepointSynchronize::is_at_safepoint(), "Heap verification must be done at a safepoint");
+  verify_heap("at safepoint");
+}
 
 void MMTkHeap::scan_universe_roots(OopClosure& cl) {
   Universe::oops_do(&cl);
@@ -471,13 +665,25 @@ void MMTkHeap::scan_code_cache_roots(OopClosure& cl) {
 void MMTkHeap::scan_string_table_roots(OopClosure& cl) {
   StringTable::oops_do(&cl);
 }
+// Interned strings are weakly referenced.  This clears the entries of dead strings, and updates
+// the others.
+void MMTkHeap::process_string_table(BoolObjectClosure* is_alive, OopClosure* update) {
+  StringTable::unlink_or_oops_do(is_alive, update);
+}
 void MMTkHeap::scan_class_loader_data_graph_roots(OopClosure& cl) {
   CLDToOopClosure cld_cl(&cl, false);
   ClassLoaderDataGraph::cld_do(&cld_cl);
 }
+// Only used for verifying the heap.  During GC, these are weak roots.
 void MMTkHeap::scan_weak_processor_roots(OopClosure& cl) {
   ResourceMark rm;
-  WeakProcessor::oops_do(&cl); // (really needed???)
+  WeakProcessor::oops_do(&cl);
+}
+// JNI weak global references and the other weak OopStorages.  This clears the entries of dead
+// objects, and updates the others.
+void MMTkHeap::process_weak_processor_roots(BoolObjectClosure* is_alive, OopClosure* update) {
+  ResourceMark rm;
+  WeakProcessor::weak_oops_do(is_alive, update);
 }
 void MMTkHeap::scan_vm_thread_roots(OopClosure& cl) {
   ResourceMark rm;
@@ -524,11 +730,77 @@ void MMTkHeap::scan_roots(OopClosure& cl) {
   WeakProcessor::oops_do(&cl);
 }
 
+// The allocation site of the current obj_allocate or array_allocate call.  We use the klass of the
+// object as the site for pretenuring.
+static THREAD_LOCAL_DECL size_t _allocation_site = MMTK_NO_ALLOCATION_SITE;
+
+class MMTkAllocationSiteMark : public StackObj {
+  size_t _saved_site;
+public:
+  MMTkAllocationSiteMark(Klass* klass) : _saved_site(_allocation_site) {
+    _allocation_site = (size_t) klass;
+  }
+  ~MMTkAllocationSiteMark() {
+    _allocation_site = _saved_site;
+  }
+};
+
 HeapWord* MMTkHeap::mem_allocate(size_t size, bool* gc_overhead_limit_was_exceeded) {
-  HeapWord* obj = Thread::current()->third_party_heap_mutator.alloc(size << LogHeapWordSize);
+  update_allocation_sampling_interval();
+  HeapWord* obj = Thread::current()->third_party_heap_mutator.alloc(size << LogHeapWordSize, AllocatorDefault, _allocation_site);
   return obj;
 }
 
+// The object sampled by MMTk in the current allocation, if any
+static THREAD_LOCAL_DECL HeapWord* _pending_allocation_sample = NULL;
+
+void MMTkHeap::record_allocation_sample(Thread* thread, HeapWord* obj) {
+  assert(thread == Thread::current(), "MMTk samples allocations of the current thread");
+  _pending_allocation_sample = obj;
+}
+
+oop MMTkHeap::post_allocation_sample(oop obj) {
+  HeapWord* sampled = _pending_allocation_sample;
+  if (sampled == NULL) {
+    return obj;
+  }
+  _pending_allocation_sample = NULL;
+  if (sampled != (HeapWord*) obj || !JvmtiExport::should_post_sampled_object_alloc()) {
+    return obj;
+  }
+  // The agent may allocate (and trigger GC) in the callback.
+  Handle obj_h(Thread::current(), obj);
+  {
+    // The event is posted when the collector is destroyed.
+    JvmtiSampledObjectAllocEventCollector collector;
+    JvmtiExport::sampled_object_alloc_event_collector(obj_h());
+  }
+  return obj_h();
+}
+
+oop MMTkHeap::obj_allocate(Klass* klass, int size, TRAPS) {
+  oop obj;
+  {
+    MMTkAllocationSiteMark mark(klass);
+    obj = CollectedHeap::obj_allocate(klass, size, CHECK_NULL);
+  }
+  return post_allocation_sample(obj);
+}
+
+oop MMTkHeap::array_allocate(Klass* klass, int size, int length, bool do_zero, TRAPS) {
+  oop obj;
+  {
+    MMTkAllocationSiteMark mark(klass);
+    obj = CollectedHeap::array_allocate(klass, size, length, do_zero, CHECK_NULL);
+  }
+  return post_allocation_sample(obj);
+}
+
+oop MMTkHeap::class_allocate(Klass* klass, int size, TRAPS) {
+  oop obj = CollectedHeap::class_allocate(klass, size, CHECK_NULL);
+  return post_allocation_sample(obj);
+}
+
 HeapWord* MMTkHeap::mem_allocate_nonmove(size_t size, bool* gc_overhead_limit_was_exceeded) {
   return Thread::current()->third_party_heap_mutator.alloc(size << LogHeapWordSize, AllocatorLos);
 }
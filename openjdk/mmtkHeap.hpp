@@ -49,8 +49,12 @@ class MMTkVMCompanionThread;
 class MMTkHeap : public CollectedHeap {
   MMTkCollectorPolicy* _collector_policy;
   SoftRefPolicy _soft_ref_policy;
-  MMTkMemoryPool* _mmtk_pool;
-  GCMemoryManager* _mmtk_manager;
+  MMTkMemoryPool* _mmtk_pools[MMTK_MAX_MEMORY_POOLS];
+  size_t _num_mmtk_pools;
+  GCMemoryManager* _mmtk_nursery_manager; // NULL if the plan is not generational
+  GCMemoryManager* _mmtk_full_manager;
+  GCMemoryManager* _mmtk_current_manager; // The manager of the GC in progress
+  GCCause::Cause _requested_gc_cause;      // The cause of the last GC requested by collect()
   HeapWord* _start;
   HeapWord* _end;
   static MMTkHeap* _heap;
@@ -59,6 +63,8 @@ class MMTkHeap : public CollectedHeap {
   ContiguousSpace* _space;
   int _num_root_scan_tasks;
   MMTkVMCompanionThread* _companion_thread;
+  bool _verify_before_gc;
+  bool _verify_after_gc;
 public:
 
   MMTkHeap(MMTkCollectorPolicy* policy);
@@ -77,6 +83,14 @@ public:
   virtual HeapWord* mem_allocate(size_t size, bool* gc_overhead_limit_was_exceeded);
   HeapWord* mem_allocate_nonmove(size_t size, bool* gc_overhead_limit_was_exceeded);
 
+  // Allocation sampling.  MMTk samples an object before it is initialized.  We post the
+  // JVMTI SampledObjectAlloc event after the allocation in obj_allocate and friends.
+  static void record_allocation_sample(Thread* thread, HeapWord* obj);
+  oop post_allocation_sample(oop obj);
+  virtual oop obj_allocate(Klass* klass, int size, TRAPS);
+  virtual oop array_allocate(Klass* klass, int size, int length, bool do_zero, TRAPS);
+  virtual oop class_allocate(Klass* klass, int size, TRAPS);
+
   MMTkVMCompanionThread* companion_thread() const {
     return _companion_thread;
   }
@@ -110,6 +124,9 @@ public:
   // The amount of used space for thread-local allocation buffers for the given thread.
   size_t tlab_used(Thread *thr) const;
 
+  size_t unsafe_max_tlab_alloc(Thread *thr) const;
+  HeapWord* allocate_new_tlab(size_t min_size, size_t requested_size, size_t* actual_size);
+
   void new_collector_thread() {
     _n_workers += 1;
   }
@@ -166,6 +183,18 @@ private:
 
 public:
 
+  // Heap verification.  Enabled by -XX:+VerifyBeforeGC and -XX:+VerifyAfterGC, or by the
+  // environment variables MMTK_VERIFY_BEFORE_GC and MMTK_VERIFY_AFTER_GC.
+  bool verify_before_gc() const { return _verify_before_gc; }
+  bool verify_after_gc() const { return _verify_after_gc; }
+  void verify_heap(const char* when);
+
+  // Update the memory managers at the start and the end of a GC.
+  // Called by GC workers while the mutators are stopped.  gc_end_serviceability is called by the
+  // GC listener registered in initialize_serviceability.
+  void gc_begin_serviceability();
+  void gc_end_serviceability(const MMTkGCEvent* event);
+
   // Print heap information on the given outputStream.
   void print_on(outputStream* st) const ;
 
@@ -208,8 +237,10 @@ public:
   void scan_system_dictionary_roots(OopClosure& cl);
   void scan_code_cache_roots(OopClosure& cl);
   void scan_string_table_roots(OopClosure& cl);
+  void process_string_table(BoolObjectClosure* is_alive, OopClosure* update);
   void scan_class_loader_data_graph_roots(OopClosure& cl);
   void scan_weak_processor_roots(OopClosure& cl);
+  void process_weak_processor_roots(BoolObjectClosure* is_alive, OopClosure* update);
   void scan_vm_thread_roots(OopClosure& cl);
 
   jlong _last_gc_time;
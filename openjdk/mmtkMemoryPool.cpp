@@ -25,19 +25,36 @@
 #include "precompiled.hpp"
 #include "mmtkMemoryPool.hpp"
 
-MMTkMemoryPool::MMTkMemoryPool(HeapWord* start, HeapWord* end,
-                               const char* name, size_t init_size,
+MMTkMemoryPool::MMTkMemoryPool(size_t space_index, const char* name,
+                               size_t init_size, size_t max_size,
                                bool support_usage_threshold) :
-  CollectedMemoryPool(name, init_size,
-                      pointer_delta(start, end)*HeapWordSize, support_usage_threshold),
-            
-        _start(start), _end(end), _used_in_bytes(0){
+  CollectedMemoryPool(name, init_size, max_size, support_usage_threshold),
+  _space_index(space_index) {
+}
+
+bool MMTkMemoryPool::space_usage(MMTkSpaceUsage* usage) const {
+  MMTkSpaceUsage spaces[MMTK_MAX_MEMORY_POOLS];
+  size_t n = mmtk_get_space_usage(spaces, MMTK_MAX_MEMORY_POOLS);
+  if (_space_index >= MIN2(n, (size_t) MMTK_MAX_MEMORY_POOLS)) {
+    return false;
+  }
+  *usage = spaces[_space_index];
+  return true;
+}
+
+size_t MMTkMemoryPool::used_in_bytes() {
+  MMTkSpaceUsage usage;
+  return space_usage(&usage) ? usage.used : 0;
 }
 
 MemoryUsage MMTkMemoryPool::get_memory_usage() {
+  MMTkSpaceUsage usage;
+  if (!space_usage(&usage)) {
+    return MemoryUsage(initial_size(), 0, 0, max_size());
+  }
   size_t maxSize   = (available_for_allocation() ? max_size() : 0);
-  size_t used      = used_in_bytes();
-  size_t committed = pointer_delta(_start, _end)*HeapWordSize;
+  // MMTk may count pages that are reserved for allocation but not yet mapped as used.
+  size_t committed = MAX2(usage.committed, usage.used);
 
-  return MemoryUsage(initial_size(), used, committed, maxSize);
+  return MemoryUsage(initial_size(), usage.used, committed, maxSize);
 }
@@ -25,21 +25,25 @@
 #ifndef MMTK_OPENJDK_MMTK_MEMORY_POOL_HPP
 #define MMTK_OPENJDK_MMTK_MEMORY_POOL_HPP
 
+#include "mmtk.h"
 #include "services/memoryPool.hpp"
 #include "services/memoryUsage.hpp"
 
+// The maximum number of spaces in an MMTk plan that we expose as memory pools.
+#define MMTK_MAX_MEMORY_POOLS 16
+
+// A memory pool for one MMTk space.  The usage is queried from the MMTk binding.
 class MMTkMemoryPool : public CollectedMemoryPool {
 private:
-  HeapWord* _start;
-  HeapWord* _end;
-  size_t _used_in_bytes;
+  size_t _space_index;
+
+  bool space_usage(MMTkSpaceUsage* usage) const;
 
 public:
-  MMTkMemoryPool(HeapWord* start, HeapWord* end, const char* name, size_t init_size,  bool support_usage_threshold);
+  MMTkMemoryPool(size_t space_index, const char* name, size_t init_size, size_t max_size, bool support_usage_threshold);
 
   MemoryUsage get_memory_usage();
-  size_t used_in_bytes() { return _used_in_bytes; }
-  size_t max_size() const { return pointer_delta(_start, _end)*HeapWordSize; }
+  size_t used_in_bytes();
 };
 
 
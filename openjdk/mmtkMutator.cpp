@@ -10,23 +10,35 @@ MMTkMutatorContext MMTkMutatorContext::bind(::Thread* current) {
     printf("ERROR: Unmatched free list allocator size: rs=%zu cpp=%zu\n", FREE_LIST_ALLOCATOR_SIZE, sizeof(FreeListAllocator));
     guarantee(false, "ERROR");
   }
-  return *((MMTkMutatorContext*) ::bind_mutator((void*) current));
+  if (MUTATOR_SIZE != offset_of(MMTkMutatorContext, tlab_start)) {
+    printf("ERROR: Unmatched mutator size: rs=%zu cpp=%zu\n", MUTATOR_SIZE, offset_of(MMTkMutatorContext, tlab_start));
+    guarantee(false, "ERROR");
+  }
+  // The Rust mutator does not have the fields after it, so we only copy MUTATOR_SIZE bytes.
+  MMTkMutatorContext context;
+  memcpy((void*) &context, ::bind_mutator((void*) current), MUTATOR_SIZE);
+  context.tlab_start = NULL;
+  context.tlab_limit = NULL;
+  return context;
 }
 
 bool MMTkMutatorContext::is_ready_to_bind() {
   return ::openjdk_is_gc_initialized();
 }
 
-HeapWord* MMTkMutatorContext::alloc(size_t bytes, Allocator allocator) {
+HeapWord* MMTkMutatorContext::alloc(size_t bytes, Allocator allocator, size_t site) {
   // All allocations with size larger than max non-los bytes will get to this slowpath here.
   // We will use LOS for those.
   assert(MMTkMutatorContext::max_non_los_default_alloc_bytes != 0, "max_non_los_default_alloc_bytes hasn't been initialized");
   if (bytes >= MMTkMutatorContext::max_non_los_default_alloc_bytes) {
     allocator = AllocatorLos;
   }
+  // A GC in the allocation slow path may change the pretenuring decision of the site, so we
+  // select the allocator once and use it for both alloc and post_alloc.
+  allocator = (Allocator) ::mmtk_select_allocation_semantics(allocator, site);
 
   // FIXME: Proper use of slow-path api
-  HeapWord* o = (HeapWord*) ::alloc((MMTk_Mutator) this, bytes, HeapWordSize, 0, allocator);
+  HeapWord* o = (HeapWord*) ::alloc((MMTk_Mutator) this, bytes, MinObjAlignmentInBytes, 0, allocator, site);
   // Post allocation hooks. Note that we can get a nullptr from mmtk core in the case of OOM.
   // Hence, only call post allocation hooks if we have a proper object.
   if (o != nullptr) {
@@ -39,6 +51,10 @@ void MMTkMutatorContext::flush() {
   ::flush_mutator((MMTk_Mutator) this);
 }
 
+MMTkTLAB MMTkMutatorContext::tlab() {
+  return ::mmtk_get_tlab((MMTk_Mutator) this);
+}
+
 void MMTkMutatorContext::destroy() {
   ::destroy_mutator((MMTk_Mutator) this);
 }
@@ -112,11 +112,19 @@ struct MMTkMutatorContext {
   RustDynPtr plan;
   MutatorConfig config;
 
-  HeapWord* alloc(size_t bytes, Allocator allocator = AllocatorDefault);
+  // The following fields are not part of the Rust Mutator.  The binding records the start of the
+  // TLAB here, and the TLAB limit at the time.  See mmtk_openjdk::tlab.
+  void* tlab_start;
+  void* tlab_limit;
+
+  HeapWord* alloc(size_t bytes, Allocator allocator = AllocatorDefault, size_t site = MMTK_NO_ALLOCATION_SITE);
 
   void flush();
   void destroy();
 
+  // The bump-pointer buffer of the default allocator, reported to HotSpot as the TLAB of the thread.
+  MMTkTLAB tlab();
+
   static MMTkMutatorContext bind(::Thread* current);
   static bool is_ready_to_bind();
 
@@ -0,0 +1,100 @@
+/*
+ * Copyright (c) 1998, 2017, Oracle and/or its affiliates. All rights reserved.
+ * DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+ *
+ * This code is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License version 2 only, as
+ * published by the Free Software Foundation.
+ *
+ * This code is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+ * version 2 for more details (a copy is included in the LICENSE file that
+ * accompanied this code).
+ *
+ * You should have received a copy of the GNU General Public License version
+ * 2 along with this work; if not, write to the Free Software Foundation,
+ * Inc., 51 Franklin St, Fifth Floor, Boston, MA 02110-1301 USA.
+ *
+ * Please contact Oracle, 500 Oracle Parkway, Redwood Shores, CA 94065 USA
+ * or visit www.oracle.com if you need additional information or have any
+ * questions.
+ *
+ */
+
+#include "precompiled.hpp"
+#include "classfile/javaClasses.hpp"
+#include "classfile/systemDictionary.hpp"
+#include "classfile/vmSymbols.hpp"
+#include "logging/log.hpp"
+#include "mmtk.h"
+#include "mmtkPeriodicGCThread.hpp"
+#include "runtime/interfaceSupport.inline.hpp"
+#include "runtime/javaCalls.hpp"
+#include "runtime/mutexLocker.hpp"
+#include "runtime/os.hpp"
+
+MMTkPeriodicGCThread* MMTkPeriodicGCThread::instance = NULL;
+
+void MMTkPeriodicGCThread::initialize() {
+  EXCEPTION_MARK;
+
+  HandleMark hm;
+
+  const char* name = "MMTk Periodic GC Thread";
+  Handle string = java_lang_String::create_from_str(name, CHECK);
+
+  // Initialize thread_oop to put it into the system threadGroup
+  Handle thread_group (THREAD, Universe::system_thread_group());
+  Handle thread_oop = JavaCalls::construct_new_instance(SystemDictionary::Thread_klass(),
+                                                        vmSymbols::threadgroup_string_void_signature(),
+                                                        thread_group,
+                                                        string,
+                                                        CHECK);
+
+  {
+    MutexLocker mu(Threads_lock);
+    MMTkPeriodicGCThread* thread = new MMTkPeriodicGCThread(&periodic_gc_thread_entry);
+
+    // At this point it may be possible that no osthread was created for the
+    // JavaThread due to lack of memory. We would have to throw an exception
+    // in that case. However, since this must work and we do not allow
+    // exceptions anyway, check and abort if this fails.
+    if (thread == NULL || thread->osthread() == NULL) {
+      vm_exit_during_initialization("java.lang.OutOfMemoryError",
+                                    os::native_thread_creation_failed_msg());
+    }
+
+    java_lang_Thread::set_thread(thread_oop(), thread);
+    java_lang_Thread::set_priority(thread_oop(), NearMaxPriority);
+    java_lang_Thread::set_daemon(thread_oop());
+    thread->set_threadObj(thread_oop());
+    instance = thread;
+
+    Threads::add(thread);
+    Thread::start(thread);
+  }
+}
+
+void MMTkPeriodicGCThread::periodic_gc_thread_entry(JavaThread* thread, TRAPS) {
+  MMTkPeriodicGCThread* this_thread = MMTkPeriodicGCThread::instance;
+  while (true) {
+    // Perform a GC if one is due.  This blocks until the GC finishes, like MMTkHeap::collect().
+    uint64_t delay_ms = mmtk_periodic_gc_poll((void*) thread);
+    if (delay_ms == 0) {
+      log_info(gc)("Periodic GC is disabled.  Stopping the periodic GC thread.");
+      return;
+    }
+
+    // Wait until the next poll
+    {
+      ThreadBlockInVM tbivm(thread);
+      MutexLockerEx mu(this_thread->m, Mutex::_no_safepoint_check_flag);
+      this_thread->m->wait(Mutex::_no_safepoint_check_flag, (long) delay_ms);
+    }
+  }
+}
+
+MMTkPeriodicGCThread::MMTkPeriodicGCThread(ThreadFunction entry_point) : JavaThread(entry_point) {
+  this->m = new Monitor(Mutex::suspend_resume, "mmtk-periodic-gc-monitor", true, Monitor::_safepoint_check_never);
+}
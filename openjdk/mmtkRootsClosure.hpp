@@ -55,6 +55,36 @@ public:
   virtual void do_oop(narrowOop* p) { do_oop_work(p, true); }
 };
 
+// Weak roots are processed after the transitive closure with these two closures: entries whose
+// objects are dead are cleared, and the others are updated to the new addresses of the objects.
+class MMTkWeakRootsIsAliveClosure : public BoolObjectClosure {
+  WeakRootsClosure _weak_roots_closure;
+
+public:
+  MMTkWeakRootsIsAliveClosure(WeakRootsClosure weak_roots_closure): _weak_roots_closure(weak_roots_closure) {}
+
+  virtual bool do_object_b(oop obj) {
+    return _weak_roots_closure.invoke((void*) obj) != NULL;
+  }
+};
+
+class MMTkWeakRootsUpdateClosure : public OopClosure {
+  WeakRootsClosure _weak_roots_closure;
+
+public:
+  MMTkWeakRootsUpdateClosure(WeakRootsClosure weak_roots_closure): _weak_roots_closure(weak_roots_closure) {}
+
+  virtual void do_oop(oop* p) {
+    oop obj = RawAccess<>::oop_load(p);
+    if (obj != NULL) {
+      oop new_obj = (oop) _weak_roots_closure.invoke((void*) obj);
+      guarantee(new_obj != NULL, "Only live weak roots are updated");
+      RawAccess<>::oop_store(p, new_obj);
+    }
+  }
+  virtual void do_oop(narrowOop* p) { ShouldNotReachHere(); }
+};
+
 class MMTkScanObjectClosure : public BasicOopIterateClosure {
   void* _trace;
   CLDToOopClosure follow_cld_closure;
@@ -24,15 +24,20 @@
 
 #include "precompiled.hpp"
 #include "classfile/stringTable.hpp"
+#include "classfile/systemDictionary.hpp"
+#include "code/codeCache.hpp"
 #include "code/nmethod.hpp"
 #include "memory/iterator.inline.hpp"
 #include "memory/resourceArea.hpp"
+#include "memory/universe.hpp"
 #include "mmtkCollectorThread.hpp"
 #include "mmtkHeap.hpp"
 #include "mmtkRootsClosure.hpp"
 #include "mmtkUpcalls.hpp"
 #include "mmtkVMCompanionThread.hpp"
+#include "oops/fieldStreams.hpp"
 #include "runtime/atomic.hpp"
+#include "runtime/fieldType.hpp"
 #include "runtime/mutexLocker.hpp"
 #include "runtime/os.hpp"
 #include "runtime/safepoint.hpp"
@@ -47,14 +52,21 @@ static volatile size_t mmtk_start_the_world_count = 0;
 static void mmtk_stop_all_mutators(void *tls, MutatorClosure closure) {
   ClassLoaderDataGraph::clear_claimed_marks();
   CodeCache::gc_prologue();
-#if COMPILER2_OR_JVMCI
-  DerivedPointerTable::clear();
-#endif
 
   log_debug(gc)("Requesting the VM to suspend all mutators...");
   MMTkHeap::heap()->companion_thread()->request(MMTkVMCompanionThread::_threads_suspended, true);
   log_debug(gc)("Mutators stopped. Now enumerate threads for scanning...");
 
+  // Verify before the derived pointer table is activated.  Otherwise, the stack scanning of the
+  // verifier would add derived pointers to the table.
+  if (MMTkHeap::heap()->verify_before_gc()) {
+    MMTkHeap::heap()->verify_heap("before GC");
+  }
+#if COMPILER2_OR_JVMCI
+  DerivedPointerTable::clear();
+#endif
+  MMTkHeap::heap()->gc_begin_serviceability();
+
   JavaThreadIteratorWithHandle jtiwh;
   while (JavaThread *cur = jtiwh.next()) {
     closure.invoke((void*)&cur->third_party_heap_mutator);
@@ -64,15 +76,47 @@ static void mmtk_stop_all_mutators(void *tls, MutatorClosure closure) {
   nmethod::oops_do_marking_prologue();
 }
 
+// On x86, oops embedded in compiled code are immediates, and MMTk updates them in place.  On RISC
+// architectures (AArch64, RISC-V), the instructions materialize oops, compressed or not, from the
+// oop table of the nmethod.  MMTk only updates the table, so we patch the instructions from it.
+static void mmtk_fix_nmethod_oop_relocations() {
+#ifndef X86
+  MutexLockerEx locker(CodeCache_lock, Mutex::_no_safepoint_check_flag);
+  NMethodIterator iter;
+  while (iter.next_alive()) {
+    iter.method()->fix_oop_relocations();
+  }
+#endif
+}
+
+// Advance SoftReference.clock, which SoftReference.get() copies into the timestamp of the
+// reference, as ReferenceProcessor::update_soft_ref_master_clock does.  The binding clears soft
+// references that have not been used for a while.
+static void mmtk_update_soft_reference_clock() {
+  jlong now = os::javaTimeNanos() / NANOSECS_PER_MILLISEC;
+  if (now > java_lang_ref_SoftReference::clock()) {
+    java_lang_ref_SoftReference::set_clock(now);
+  }
+}
+
 static void mmtk_resume_mutators(void *tls) {
   nmethod::oops_do_marking_epilogue();
+  mmtk_fix_nmethod_oop_relocations();
+  mmtk_update_soft_reference_clock();
   // ClassLoaderDataGraph::purge();
+  // Klasses are never freed, because we do not unload classes.  If we do, we must call
+  // mmtk_invalidate_scan_plans() after purging, because a new klass may reuse the address of a
+  // freed one.
   CodeCache::gc_epilogue();
   JvmtiExport::gc_epilogue();
 #if COMPILER2_OR_JVMCI
   DerivedPointerTable::update_pointers();
 #endif
 
+  if (MMTkHeap::heap()->verify_after_gc()) {
+    MMTkHeap::heap()->verify_heap("after GC");
+  }
+
   // Note: we don't have to hold gc_lock to increment the counter.
   // The increment has to be done before mutators can be resumed (from `block_for_gc` or yieldpoints).
   // Otherwise, mutators might see an outdated start-the-world count.
@@ -155,6 +199,9 @@ static void mmtk_out_of_memory(void* tls, MMTkAllocationError err_kind) {
     //
     // [1]: https://github.com/mmtk/openjdk/blob/e4dbe9909fa5c21685a20a1bc541fcc3b050dac4/src/hotspot/share/gc/shared/memAllocator.cpp#L83
     // [2]: https://github.com/mmtk/openjdk/blob/e4dbe9909fa5c21685a20a1bc541fcc3b050dac4/src/hotspot/share/gc/shared/memAllocator.cpp#L117
+    //
+    // For the same reason, the heap dump for -XX:+HeapDumpOnOutOfMemoryError is written by HotSpot's HeapDumper
+    // when the JVM reports the OOM, after we have returned from the MMTk allocation path.
     break;
   case MmapOutOfMemory :
     // Abort the VM immediately due to insufficient system resources.
@@ -237,14 +284,58 @@ static int static_oop_field_count_offset() {
   return java_lang_Class::static_oop_field_count_offset();
 }
 
-static size_t compute_klass_mem_layout_checksum() {
-  return sizeof(Klass)
-    ^ sizeof(InstanceKlass)
-    ^ sizeof(InstanceRefKlass)
-    ^ sizeof(InstanceMirrorKlass)
-    ^ sizeof(InstanceClassLoaderKlass)
-    ^ sizeof(TypeArrayKlass)
-    ^ sizeof(ObjArrayKlass);
+// Report the values that the binding reads from `k`.  The fields behind them have no offset
+// accessors, so the binding compares the values instead.
+static void report_klass_layout_values(KlassLayoutClosure closure, Klass* k) {
+  closure.invoke("Klass::id()", (intptr_t) k->id(), k);
+  closure.invoke("Klass::layout_helper()", k->layout_helper(), k);
+  closure.invoke("Klass::vtable_length()", k->vtable_length(), k);
+  closure.invoke("Klass::super()", p2i(k->super()), k);
+  closure.invoke("Klass::java_mirror()", p2i(k->java_mirror()), k);
+  if (k->is_instance_klass()) {
+    InstanceKlass* ik = InstanceKlass::cast(k);
+    closure.invoke("InstanceKlass::itable_length()", ik->itable_length(), k);
+    closure.invoke("InstanceKlass::nonstatic_oop_map_count()", ik->nonstatic_oop_map_count(), k);
+    closure.invoke("InstanceKlass::reference_type()", (intptr_t) ik->reference_type(), k);
+    closure.invoke("InstanceKlass::start_of_nonstatic_oop_maps()",
+                   (intptr_t) pointer_delta(ik->start_of_nonstatic_oop_maps(), ik, 1), k);
+  }
+}
+
+static void mmtk_report_klass_mem_layout(KlassLayoutClosure closure) {
+  closure.invoke("sizeof(Klass)", sizeof(Klass), NULL);
+  closure.invoke("sizeof(InstanceKlass)", sizeof(InstanceKlass), NULL);
+  closure.invoke("sizeof(InstanceRefKlass)", sizeof(InstanceRefKlass), NULL);
+  closure.invoke("sizeof(InstanceMirrorKlass)", sizeof(InstanceMirrorKlass), NULL);
+  closure.invoke("sizeof(InstanceClassLoaderKlass)", sizeof(InstanceClassLoaderKlass), NULL);
+  closure.invoke("sizeof(TypeArrayKlass)", sizeof(TypeArrayKlass), NULL);
+  closure.invoke("sizeof(ObjArrayKlass)", sizeof(ObjArrayKlass), NULL);
+
+  closure.invoke("Klass::_layout_helper", in_bytes(Klass::layout_helper_offset()), NULL);
+  closure.invoke("Klass::_super", in_bytes(Klass::super_offset()), NULL);
+  closure.invoke("Klass::_java_mirror", in_bytes(Klass::java_mirror_offset()), NULL);
+  closure.invoke("Klass::_vtable_len", in_bytes(Klass::vtable_length_offset()), NULL);
+  closure.invoke("InstanceKlass::vtable_start_offset()", in_bytes(InstanceKlass::vtable_start_offset()), NULL);
+  closure.invoke("oopDesc::klass_offset_in_bytes()", oopDesc::klass_offset_in_bytes(), NULL);
+  closure.invoke("arrayOopDesc::length_offset_in_bytes()", arrayOopDesc::length_offset_in_bytes(), NULL);
+  closure.invoke("arrayOopDesc::base_offset_in_bytes(T_OBJECT)", arrayOopDesc::base_offset_in_bytes(T_OBJECT), NULL);
+
+  // One klass of each kind the binding scans
+  Klass* klasses[] = {
+    SystemDictionary::Object_klass(),
+    SystemDictionary::String_klass(),
+    SystemDictionary::Class_klass(),
+    SystemDictionary::ClassLoader_klass(),
+    SystemDictionary::SoftReference_klass(),
+    SystemDictionary::WeakReference_klass(),
+    SystemDictionary::FinalReference_klass(),
+    SystemDictionary::PhantomReference_klass(),
+    Universe::objectArrayKlassObj(),
+    Universe::intArrayKlassObj(),
+  };
+  for (size_t i = 0; i < ARRAY_SIZE(klasses); i++) {
+    report_klass_layout_values(closure, klasses[i]);
+  }
 }
 
 static int referent_offset() {
@@ -255,6 +346,22 @@ static int discovered_offset() {
   return java_lang_ref_Reference::discovered_offset;
 }
 
+static int next_offset() {
+  return java_lang_ref_Reference::next_offset;
+}
+
+static int soft_reference_timestamp_offset() {
+  return java_lang_ref_SoftReference::timestamp_offset;
+}
+
+static int soft_reference_clock_offset() {
+  return java_lang_ref_SoftReference::static_clock_offset;
+}
+
+static void* soft_reference_mirror() {
+  return (void*) SystemDictionary::SoftReference_klass()->java_mirror();
+}
+
 static char* dump_object_string(void* object) {
   oop o = (oop) object;
   return o->print_value_string();
@@ -277,6 +384,18 @@ static void mmtk_scan_class_loader_data_graph_roots(SlotsClosure closure) { MMTk
 static void mmtk_scan_weak_processor_roots(SlotsClosure closure) { MMTkRootsClosure cl(closure); MMTkHeap::heap()->scan_weak_processor_roots(cl); }
 static void mmtk_scan_vm_thread_roots(SlotsClosure closure) { MMTkRootsClosure cl(closure); MMTkHeap::heap()->scan_vm_thread_roots(cl); }
 
+static void mmtk_process_string_table(WeakRootsClosure closure) {
+  MMTkWeakRootsIsAliveClosure is_alive(closure);
+  MMTkWeakRootsUpdateClosure update(closure);
+  MMTkHeap::heap()->process_string_table(&is_alive, &update);
+}
+
+static void mmtk_process_weak_processor_roots(WeakRootsClosure closure) {
+  MMTkWeakRootsIsAliveClosure is_alive(closure);
+  MMTkWeakRootsUpdateClosure update(closure);
+  MMTkHeap::heap()->process_weak_processor_roots(&is_alive, &update);
+}
+
 static size_t mmtk_number_of_mutators() {
   return Threads::number_of_threads();
 }
@@ -327,7 +446,42 @@ static void mmtk_enqueue_references(void** objects, size_t len) {
   HeapAccess<AS_RAW>::oop_store_at(last, java_lang_ref_Reference::discovered_offset, old_first);
 }
 
+static int mirror_klass_offset() {
+  return java_lang_Class::klass_offset_in_bytes();
+}
+
+// Copy the name of the klass into `buf`, NUL-terminated and truncated to `len` bytes.  Returns the
+// length of the name, which is at least `len` if the name was truncated.
+static size_t mmtk_klass_name(void* klass, char* buf, size_t len) {
+  Symbol* name = ((Klass*) klass)->name();
+  name->as_C_string(buf, (int) len);
+  return (size_t) name->utf8_length();
+}
+
+static void* mmtk_klass_class_loader(void* klass) {
+  return (void*) ((Klass*) klass)->class_loader();
+}
+
+static void mmtk_iterate_klass_fields(void* klass, FieldClosure closure) {
+  Klass* k = (Klass*) klass;
+  if (!k->is_instance_klass()) {
+    return;
+  }
+  ResourceMark rm;
+  for (JavaFieldStream fs(InstanceKlass::cast(k)); !fs.done(); fs.next()) {
+    BasicType type = FieldType::basic_type(fs.signature());
+    closure.invoke(fs.name()->as_C_string(), (int) type, fs.offset(), fs.access_flags().is_static());
+  }
+}
+
+static void mmtk_sample_allocation(void* tls, void* object, size_t bytes) {
+  // The object is not initialized yet.  MMTkHeap posts the sample after initializing it.
+  MMTkHeap::record_allocation_sample((Thread*) tls, (HeapWord*) object);
+}
+
 OpenJDK_Upcalls mmtk_upcalls = {
+  MMTK_UPCALLS_VERSION,
+  sizeof(OpenJDK_Upcalls),
   mmtk_stop_all_mutators,
   mmtk_resume_mutators,
   mmtk_spawn_gc_thread,
@@ -341,11 +495,15 @@ OpenJDK_Upcalls mmtk_upcalls = {
   mmtk_is_mutator,
   mmtk_harness_begin,
   mmtk_harness_end,
-  compute_klass_mem_layout_checksum,
+  mmtk_report_klass_mem_layout,
   offset_of_static_fields,
   static_oop_field_count_offset,
   referent_offset,
   discovered_offset,
+  next_offset,
+  soft_reference_timestamp_offset,
+  soft_reference_clock_offset,
+  soft_reference_mirror,
   dump_object_string,
   mmtk_scan_roots_in_all_mutator_threads,
   mmtk_scan_roots_in_mutator_thread,
@@ -358,11 +516,18 @@ OpenJDK_Upcalls mmtk_upcalls = {
   mmtk_scan_system_dictionary_roots,
   mmtk_scan_code_cache_roots,
   mmtk_scan_string_table_roots,
+  mmtk_process_string_table,
   mmtk_scan_class_loader_data_graph_roots,
   mmtk_scan_weak_processor_roots,
+  mmtk_process_weak_processor_roots,
   mmtk_scan_vm_thread_roots,
   mmtk_number_of_mutators,
   mmtk_schedule_finalizer,
   mmtk_prepare_for_roots_re_scanning,
-  mmtk_enqueue_references
+  mmtk_enqueue_references,
+  mirror_klass_offset,
+  mmtk_klass_name,
+  mmtk_klass_class_loader,
+  mmtk_iterate_klass_fields,
+  mmtk_sample_allocation
 };
@@ -1,6 +1,9 @@
 #include "precompiled.hpp"
 #include "gc/shared/thirdPartyHeap.hpp"
+#include "memory/universe.hpp"
 #include "mmtk.h"
+#include "runtime/handles.inline.hpp"
+#include "runtime/javaCalls.hpp"
 #include "thirdPartyHeap.hpp"
 #include "thirdPartyHeapArguments.hpp"
 
@@ -10,8 +13,17 @@ GCArguments* new_gc_arguments() {
   return NULL;
 }
 
+// Register a finalizable object as HotSpot's own collectors do: Finalizer.register creates a
+// java.lang.ref.Finalizer for it, which the binding discovers during GC.  The Java call may
+// trigger a GC, so the caller must hold `obj` in a handle.
 void register_finalizer(void* obj) {
-  add_finalizer(obj);
+  JavaThread* THREAD = JavaThread::current();
+  HandleMark hm(THREAD);
+  instanceHandle h_obj(THREAD, (instanceOop) obj);
+  JavaValue result(T_VOID);
+  JavaCallArguments args(h_obj);
+  methodHandle mh(THREAD, Universe::finalizer_register_method());
+  JavaCalls::call(&result, mh, &args, THREAD);
 }
 
 };
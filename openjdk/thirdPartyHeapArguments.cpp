@@ -42,7 +42,10 @@ size_t ThirdPartyHeapArguments::conservative_max_heap_alignment() {
 void ThirdPartyHeapArguments::initialize() {
   GCArguments::initialize();
   assert(UseThirdPartyHeap , "Error, should UseThirdPartyHeap");
-  FLAG_SET_DEFAULT(UseTLAB, false);
+  // HotSpot TLABs are optional: MMTk allocators have their own buffers, which are reported as TLABs.
+  if (FLAG_IS_DEFAULT(UseTLAB)) {
+    FLAG_SET_DEFAULT(UseTLAB, false);
+  }
   FLAG_SET_DEFAULT(UseCompressedClassPointers, UseCompressedOops);
   FLAG_SET_DEFAULT(ParallelGCThreads, Abstract_VM_Version::parallel_worker_threads());
   if (ParallelGCThreads == 0) {